@@ -3,6 +3,33 @@
 
 BEGIN;
 
+-- Create extensions
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Create functions
+
+-- `ip::INET` that's NULL for a malformed address instead of failing the whole query
+CREATE FUNCTION try_inet(ip TEXT) RETURNS INET AS $$
+BEGIN
+  RETURN ip::INET;
+EXCEPTION WHEN invalid_text_representation THEN
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+-- The description as the player wrote it, `level_desc` is kept the way the game sends it
+-- (URL-safe base64, not always padded). Empty if it doesn't decode.
+CREATE FUNCTION decode_description(level_desc TEXT) RETURNS TEXT AS $$
+BEGIN
+  RETURN convert_from(
+    decode(rpad(translate(level_desc, '-_', '+/'), (length(level_desc) + 3) / 4 * 4, '='), 'base64'),
+    'UTF8'
+  );
+EXCEPTION WHEN OTHERS THEN
+  RETURN '';
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
 --
 -- Table structure for table 'acc_comments'
 --
@@ -43,6 +70,16 @@ CREATE TABLE accounts (
   is_active BOOLEAN NOT NULL DEFAULT FALSE
 );
 
+--
+-- Table structure for table 'account_deletions'
+--
+
+CREATE TABLE account_deletions (
+  account_id INT PRIMARY KEY,
+  requested_at INT NOT NULL,
+  delete_at INT NOT NULL
+);
+
 --
 -- Table structure for table 'actions'
 --
@@ -93,6 +130,30 @@ CREATE TABLE banned_ips (
   id SERIAL PRIMARY KEY
 );
 
+--
+-- Table structure for table 'bans'
+--
+
+CREATE TABLE bans (
+  ban_id SERIAL PRIMARY KEY,
+  account_id INT NOT NULL,
+  scope SMALLINT NOT NULL, -- 0=LOGIN 1=LEADERBOARD 2=CREATOR 3=COMMENT 4=MESSAGING
+  reason VARCHAR(255) NOT NULL DEFAULT '',
+  issued_by INT NOT NULL DEFAULT 0, -- account_id of the moderator, 0 for the server itself
+  issued_at BIGINT NOT NULL,
+  expires_at BIGINT DEFAULT NULL, -- NULL means the ban is permanent
+  is_revoked BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+--
+-- Table structure for table 'blobs'
+--
+
+CREATE TABLE blobs (
+  key VARCHAR(255) PRIMARY KEY, -- e.g. levels/128 or saves/1-7, only used by the database storage backend
+  data BYTEA NOT NULL
+);
+
 --
 -- Table structure for table 'blocks'
 --
@@ -204,7 +265,7 @@ CREATE TABLE levels (
   coins INT NOT NULL DEFAULT 0,
   requested_stars INT NOT NULL DEFAULT 0,
   extra_string TEXT NOT NULL,
-  level_string TEXT,
+  level_string TEXT, -- only set on old levels, the data is kept in storage (see `storage.backend`)
   level_info TEXT NOT NULL,
   secret TEXT NOT NULL,
   star_difficulty INT NOT NULL DEFAULT 0, -- 0=N/A 10=EASY 20=NORMAL 30=HARD 40=HARDER 50=INSANE 50=AUTO 50=DEMON
@@ -457,6 +518,26 @@ CREATE TABLE roles (
   mod_badge_level INT NOT NULL DEFAULT 0
 );
 
+--
+-- Table structure for table 'save_versions'
+--
+
+CREATE TABLE save_versions (
+  version_id SERIAL PRIMARY KEY,
+  account_id INT NOT NULL,
+  size INT NOT NULL, -- in bytes, as sent by the client
+  created_at INT NOT NULL,
+  -- stats read from the save, see `SaveStats`
+  stars INT NOT NULL DEFAULT 0,
+  moons INT NOT NULL DEFAULT 0,
+  diamonds INT NOT NULL DEFAULT 0,
+  demons INT NOT NULL DEFAULT 0,
+  orbs INT NOT NULL DEFAULT 0,
+  completed_levels INT NOT NULL DEFAULT 0,
+  coins INT NOT NULL DEFAULT 0,
+  user_coins INT NOT NULL DEFAULT 0
+);
+
 --
 -- Table structure for table 'songs'
 --
@@ -490,6 +571,18 @@ CREATE TABLE suggest (
   timestamp INT NOT NULL
 );
 
+--
+-- Table structure for table 'username_changes'
+--
+
+CREATE TABLE username_changes (
+  id SERIAL PRIMARY KEY,
+  account_id INT NOT NULL,
+  old_username VARCHAR(255) NOT NULL,
+  new_username VARCHAR(255) NOT NULL,
+  timestamp INT NOT NULL
+);
+
 --
 -- Table structure for table 'users'
 --
@@ -535,9 +628,7 @@ CREATE TABLE users (
   chest1_time INT NOT NULL DEFAULT 0,
   chest2_time INT NOT NULL DEFAULT 0,
   chest1_count INT NOT NULL DEFAULT 0,
-  chest2_count INT NOT NULL DEFAULT 0,
-  is_banned INT NOT NULL DEFAULT 0,
-  is_creator_banned INT NOT NULL DEFAULT 0
+  chest2_count INT NOT NULL DEFAULT 0
 );
 
 -- Create indexes
@@ -547,6 +638,8 @@ CREATE INDEX idx_acc_comments_userid ON acc_comments(user_id);
 CREATE INDEX idx_acc_comments_timestamp ON acc_comments(timestamp);
 
 -- accounts
+-- Names differing only in case would pass for the same player
+CREATE UNIQUE INDEX idx_accounts_username_lower ON accounts(lower(username));
 CREATE INDEX idx_accounts_isadmin ON accounts(is_admin);
 CREATE INDEX idx_accounts_frs ON accounts(fr_s);
 CREATE INDEX idx_accounts_discordid ON accounts(discord_id);
@@ -559,6 +652,7 @@ CREATE INDEX idx_actions_type ON actions(type);
 CREATE INDEX idx_actions_value ON actions(value);
 CREATE INDEX idx_actions_value2 ON actions(value2);
 CREATE INDEX idx_actions_timestamp ON actions(timestamp);
+CREATE INDEX idx_actions_account ON actions(account);
 
 -- actions_downloads
 CREATE INDEX idx_actions_downloads_levelid_ip_date ON actions_downloads(level_id, ip, upload_date);
@@ -566,6 +660,9 @@ CREATE INDEX idx_actions_downloads_levelid_ip_date ON actions_downloads(level_id
 -- actions_likes
 CREATE INDEX idx_actions_likes_item_type_islike_ip_date ON actions_likes(item_id, type, is_like, ip, upload_date);
 
+-- bans
+CREATE INDEX idx_bans_accountid_scope ON bans(account_id, scope);
+
 -- blocks
 CREATE INDEX idx_blocks_person1 ON blocks(person1);
 CREATE INDEX idx_blocks_person2 ON blocks(person2);
@@ -598,6 +695,9 @@ CREATE INDEX idx_gauntlets_level5 ON gauntlets(level5);
 
 -- levels
 CREATE INDEX idx_levels_levelname ON levels(level_name);
+CREATE INDEX idx_levels_levelname_trgm ON levels USING GIN (level_name gin_trgm_ops);
+CREATE INDEX idx_levels_text_fts ON levels USING GIN ((setweight(to_tsvector('simple', level_name), 'A') || to_tsvector('simple', decode_description(level_desc))));
+CREATE INDEX idx_levels_description_trgm ON levels USING GIN (decode_description(level_desc) gin_trgm_ops);
 CREATE INDEX idx_levels_stardifficulty ON levels(star_difficulty);
 CREATE INDEX idx_levels_starfeatured ON levels(star_featured);
 CREATE INDEX idx_levels_starepic ON levels(star_epic);
@@ -661,6 +761,9 @@ CREATE INDEX idx_role_assign_accountid ON role_assign(account_id);
 CREATE INDEX idx_roles_priority ON roles(priority);
 CREATE INDEX idx_roles_toolmodactions ON roles(tool_modactions);
 
+-- save_versions
+CREATE INDEX idx_save_versions_accountid ON save_versions(account_id);
+
 -- songs
 CREATE INDEX idx_songs_name ON songs(name);
 CREATE INDEX idx_songs_authorname ON songs(author_name);
@@ -668,6 +771,9 @@ CREATE INDEX idx_songs_authorname ON songs(author_name);
 -- suggest
 CREATE INDEX idx_suggest_timestamp ON suggest(timestamp);
 
+-- username_changes
+CREATE INDEX idx_username_changes_accountid ON username_changes(account_id);
+
 -- users
 CREATE INDEX idx_users_username ON users(username);
 CREATE INDEX idx_users_stars ON users(stars);
@@ -679,10 +785,15 @@ CREATE INDEX idx_users_creatorpoints ON users(creator_points);
 CREATE INDEX idx_users_diamonds ON users(diamonds);
 CREATE INDEX idx_users_orbs ON users(orbs);
 CREATE INDEX idx_users_completedlvls ON users(completed_lvls);
-CREATE INDEX idx_users_isbanned ON users(is_banned);
-CREATE INDEX idx_users_iscreatorbanned ON users(is_creator_banned);
 CREATE INDEX idx_users_extid ON users(ext_id);
 CREATE INDEX idx_users_ip ON users(ip);
 CREATE INDEX idx_users_isregistered ON users(is_registered);
 
+-- Create views
+
+CREATE VIEW active_bans AS
+  SELECT * FROM bans
+  WHERE is_revoked = FALSE
+    AND (expires_at IS NULL OR expires_at > EXTRACT(EPOCH FROM NOW())::BIGINT);
+
 COMMIT;
\ No newline at end of file
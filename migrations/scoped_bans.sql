@@ -0,0 +1,45 @@
+-- Upgrades a database from before scoped bans, run it once before starting the new version.
+-- `users.is_banned` kept a player out of logging in, the leaderboards, comments and messages,
+-- `users.is_creator_banned` out of the creator leaderboard. Both become permanent bans issued by
+-- the server.
+
+BEGIN;
+
+CREATE TABLE bans (
+  ban_id SERIAL PRIMARY KEY,
+  account_id INT NOT NULL,
+  scope SMALLINT NOT NULL, -- 0=LOGIN 1=LEADERBOARD 2=CREATOR 3=COMMENT 4=MESSAGING
+  reason VARCHAR(255) NOT NULL DEFAULT '',
+  issued_by INT NOT NULL DEFAULT 0, -- account_id of the moderator, 0 for the server itself
+  issued_at BIGINT NOT NULL,
+  expires_at BIGINT DEFAULT NULL, -- NULL means the ban is permanent
+  is_revoked BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX idx_bans_accountid_scope ON bans(account_id, scope);
+
+CREATE VIEW active_bans AS
+  SELECT * FROM bans
+  WHERE is_revoked = FALSE
+    AND (expires_at IS NULL OR expires_at > EXTRACT(EPOCH FROM NOW())::BIGINT);
+
+-- Only registered users have an account to ban, `ext_id` is the UDID for the others
+INSERT INTO bans (account_id, scope, reason, issued_at)
+  SELECT users.ext_id::INT, scopes.scope, 'Banned before scoped bans', EXTRACT(EPOCH FROM NOW())::BIGINT
+  FROM users
+  CROSS JOIN (VALUES (0), (1), (3), (4)) AS scopes(scope)
+  WHERE users.is_banned = 1 AND users.is_registered = 1;
+
+INSERT INTO bans (account_id, scope, reason, issued_at)
+  SELECT users.ext_id::INT, 2, 'Banned before scoped bans', EXTRACT(EPOCH FROM NOW())::BIGINT
+  FROM users
+  WHERE users.is_creator_banned = 1 AND users.is_registered = 1;
+
+DROP INDEX idx_users_isbanned;
+DROP INDEX idx_users_iscreatorbanned;
+
+ALTER TABLE users
+  DROP COLUMN is_banned,
+  DROP COLUMN is_creator_banned;
+
+COMMIT;
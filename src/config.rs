@@ -0,0 +1,496 @@
+use std::{env, fmt, fs, net::SocketAddr, path::PathBuf, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::utilities::crypto::decode_key;
+
+const DEFAULT_PATH: &str = "config.json";
+// Anyone who knows the key can sign their own links, so it has to be long enough not to be
+// guessed and can't be one of the values from examples
+const MIN_SECRET_KEY_LENGTH: usize = 32;
+const PLACEHOLDER_SECRET_KEYS: &[&str] = &["change me", "changeme", "change-me", "secret"];
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database_url: String,
+    pub listen_address: SocketAddr,
+    pub max_connections: u32,
+    // Returned by `getAccountURL.php`, the game sends save backups to this address
+    pub public_url: String,
+    // Signs the links sent out by email and keys the stored login sessions, anyone who knows it can
+    // make their own links
+    pub secret_key: String,
+    pub storage: StorageConfig,
+    pub limits: LimitsConfig,
+    pub features: FeaturesConfig,
+    pub mail: MailConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackendKind,
+    // Directories the filesystem backend keeps level data and saves in
+    pub levels: PathBuf,
+    pub saves: PathBuf,
+    // Anything that speaks the S3 API with path-style URLs, e.g. `https://s3.eu-central-1.amazonaws.com`
+    // or a local MinIO at `http://localhost:9000`
+    pub s3_endpoint: String,
+    pub s3_region: String,
+    pub s3_bucket: String,
+    pub s3_access_key: String,
+    pub s3_secret_key: String,
+    // 64 hex characters, saves are encrypted with it when set. Saves are moved over to the
+    // current key (or back to plain, without one) as they're read, so a replaced key goes into
+    // `old_save_keys` until that happened.
+    pub save_key: String,
+    pub old_save_keys: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackendKind {
+    Filesystem,
+    Database,
+    S3,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    // In bytes, as sent by the client
+    pub max_level_size: usize,
+    pub max_save_size: usize,
+    // Backups kept per account, older ones are dropped as new ones come in
+    pub save_history: u32,
+    // Failed logins before an account or address is locked out, the delay between attempts
+    // doubles on the way there
+    pub login_attempts_per_account: u32,
+    pub login_attempts_per_ip: u32,
+    // In seconds, also how far back failed logins are counted
+    pub login_lockout: i64,
+    // In seconds, how long an account has to wait between renames
+    pub username_change_interval: i64,
+    // In seconds, how long a deletion can still be cancelled before the data is gone
+    pub account_deletion_delay: i64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    pub level_uploads: bool,
+    // Only enable this when running behind a reverse proxy that sets the header,
+    // otherwise anyone can pick the address they're seen from.
+    pub trust_forwarded_for: bool,
+    // Lets several instances sharing one database clear each other's level search caches
+    pub level_cache_notify: bool,
+    // New accounts stay inactive until the link sent to their email is opened
+    pub email_verification: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    pub transport: MailTransportKind,
+    pub from: String,
+    // Where the file transport puts messages, one `.eml` file each
+    pub directory: PathBuf,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: String,
+    pub smtp_password: String,
+    // Plain SMTP is only meant for local mail sinks
+    pub smtp_tls: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransportKind {
+    Stdout,
+    File,
+    Smtp,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: String::new(),
+            listen_address: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            max_connections: 5,
+            public_url: "https://rustyserver.local".into(),
+            secret_key: String::new(),
+            storage: StorageConfig::default(),
+            limits: LimitsConfig::default(),
+            features: FeaturesConfig::default(),
+            mail: MailConfig::default(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackendKind::Filesystem,
+            levels: "data/levels".into(),
+            saves: "data/saves".into(),
+            s3_endpoint: String::new(),
+            s3_region: "us-east-1".into(),
+            s3_bucket: String::new(),
+            s3_access_key: String::new(),
+            s3_secret_key: String::new(),
+            save_key: String::new(),
+            old_save_keys: Vec::new(),
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_level_size: 8 * 1024 * 1024,
+            max_save_size: 32 * 1024 * 1024,
+            save_history: 5,
+            login_attempts_per_account: 5,
+            login_attempts_per_ip: 20,
+            login_lockout: 15 * 60,
+            username_change_interval: 30 * 24 * 60 * 60,
+            account_deletion_delay: 14 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            level_uploads: true,
+            trust_forwarded_for: false,
+            level_cache_notify: false,
+            email_verification: false,
+        }
+    }
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: MailTransportKind::Stdout,
+            from: "GDPS <noreply@rustyserver.local>".into(),
+            directory: "data/mail".into(),
+            smtp_host: "localhost".into(),
+            smtp_port: 25,
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+            smtp_tls: true,
+        }
+    }
+}
+
+impl FromStr for StorageBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "filesystem" => Ok(Self::Filesystem),
+            "database" => Ok(Self::Database),
+            "s3" => Ok(Self::S3),
+            _ => Err("expected filesystem, database or s3".into()),
+        }
+    }
+}
+
+impl FromStr for MailTransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(Self::Stdout),
+            "file" => Ok(Self::File),
+            "smtp" => Ok(Self::Smtp),
+            _ => Err("expected stdout, file or smtp".into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_json::Error),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "failed to read '{}': {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "failed to parse '{}': {e}", path.display()),
+            ConfigError::Invalid(key, reason) => write!(f, "invalid value for '{key}': {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // Reads the file at `CONFIG_PATH` (or `config.json`, if it exists) and then applies
+    // environment variables on top of it, named after the keys (e.g. `MAX_SAVE_SIZE`).
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = env::var("CONFIG_PATH").ok().map(PathBuf::from);
+
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None if fs::exists(DEFAULT_PATH).unwrap_or(false) => {
+                Self::from_file(DEFAULT_PATH.into())?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: PathBuf) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(&path).map_err(|e| ConfigError::Read(path.clone(), e))?;
+
+        serde_json::from_str(&contents).map_err(|e| ConfigError::Parse(path, e))
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        override_from_env(&mut self.database_url, "DATABASE_URL")?;
+        override_from_env(&mut self.listen_address, "LISTEN_ADDRESS")?;
+        override_from_env(&mut self.max_connections, "MAX_CONNECTIONS")?;
+        override_from_env(&mut self.public_url, "PUBLIC_URL")?;
+        override_from_env(&mut self.secret_key, "SECRET_KEY")?;
+        override_from_env(&mut self.storage.backend, "STORAGE_BACKEND")?;
+        override_from_env(&mut self.storage.levels, "LEVELS_PATH")?;
+        override_from_env(&mut self.storage.saves, "SAVES_PATH")?;
+        override_from_env(&mut self.storage.s3_endpoint, "S3_ENDPOINT")?;
+        override_from_env(&mut self.storage.s3_region, "S3_REGION")?;
+        override_from_env(&mut self.storage.s3_bucket, "S3_BUCKET")?;
+        override_from_env(&mut self.storage.s3_access_key, "S3_ACCESS_KEY")?;
+        override_from_env(&mut self.storage.s3_secret_key, "S3_SECRET_KEY")?;
+        override_from_env(&mut self.storage.save_key, "SAVE_KEY")?;
+        // Comma separated, there's no other way to write a list in a variable
+        if let Ok(raw) = env::var("OLD_SAVE_KEYS") {
+            self.storage.old_save_keys = raw
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        override_from_env(&mut self.limits.max_level_size, "MAX_LEVEL_SIZE")?;
+        override_from_env(&mut self.limits.max_save_size, "MAX_SAVE_SIZE")?;
+        override_from_env(&mut self.limits.save_history, "SAVE_HISTORY")?;
+        override_from_env(
+            &mut self.limits.login_attempts_per_account,
+            "LOGIN_ATTEMPTS_PER_ACCOUNT",
+        )?;
+        override_from_env(
+            &mut self.limits.login_attempts_per_ip,
+            "LOGIN_ATTEMPTS_PER_IP",
+        )?;
+        override_from_env(&mut self.limits.login_lockout, "LOGIN_LOCKOUT")?;
+        override_from_env(
+            &mut self.limits.username_change_interval,
+            "USERNAME_CHANGE_INTERVAL",
+        )?;
+        override_from_env(
+            &mut self.limits.account_deletion_delay,
+            "ACCOUNT_DELETION_DELAY",
+        )?;
+        override_from_env(&mut self.features.level_uploads, "LEVEL_UPLOADS")?;
+        override_from_env(
+            &mut self.features.trust_forwarded_for,
+            "TRUST_FORWARDED_FOR",
+        )?;
+        override_from_env(&mut self.features.level_cache_notify, "LEVEL_CACHE_NOTIFY")?;
+        override_from_env(&mut self.features.email_verification, "EMAIL_VERIFICATION")?;
+        override_from_env(&mut self.mail.transport, "MAIL_TRANSPORT")?;
+        override_from_env(&mut self.mail.from, "MAIL_FROM")?;
+        override_from_env(&mut self.mail.directory, "MAIL_DIRECTORY")?;
+        override_from_env(&mut self.mail.smtp_host, "SMTP_HOST")?;
+        override_from_env(&mut self.mail.smtp_port, "SMTP_PORT")?;
+        override_from_env(&mut self.mail.smtp_username, "SMTP_USERNAME")?;
+        override_from_env(&mut self.mail.smtp_password, "SMTP_PASSWORD")?;
+        override_from_env(&mut self.mail.smtp_tls, "SMTP_TLS")?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.database_url.is_empty() {
+            return Err(ConfigError::Invalid("database_url", "must be set".into()));
+        }
+        if self.max_connections == 0 {
+            return Err(ConfigError::Invalid(
+                "max_connections",
+                "must be at least 1".into(),
+            ));
+        }
+        if !self.public_url.starts_with("http://") && !self.public_url.starts_with("https://") {
+            return Err(ConfigError::Invalid(
+                "public_url",
+                format!("'{}' must start with http:// or https://", self.public_url),
+            ));
+        }
+        if self.storage.backend == StorageBackendKind::S3 {
+            if !self.storage.s3_endpoint.starts_with("http://")
+                && !self.storage.s3_endpoint.starts_with("https://")
+            {
+                return Err(ConfigError::Invalid(
+                    "storage.s3_endpoint",
+                    format!(
+                        "'{}' must start with http:// or https://",
+                        self.storage.s3_endpoint
+                    ),
+                ));
+            }
+            for (key, value) in [
+                ("storage.s3_region", &self.storage.s3_region),
+                ("storage.s3_bucket", &self.storage.s3_bucket),
+                ("storage.s3_access_key", &self.storage.s3_access_key),
+                ("storage.s3_secret_key", &self.storage.s3_secret_key),
+            ] {
+                if value.is_empty() {
+                    return Err(ConfigError::Invalid(
+                        key,
+                        "must be set for s3 storage".into(),
+                    ));
+                }
+            }
+        }
+        if !self.storage.save_key.is_empty() && decode_key(&self.storage.save_key).is_none() {
+            return Err(ConfigError::Invalid(
+                "storage.save_key",
+                "must be 64 hex characters".into(),
+            ));
+        }
+        if self
+            .storage
+            .old_save_keys
+            .iter()
+            .any(|key| decode_key(key).is_none())
+        {
+            return Err(ConfigError::Invalid(
+                "storage.old_save_keys",
+                "every key must be 64 hex characters".into(),
+            ));
+        }
+        if self.limits.max_level_size == 0 {
+            return Err(ConfigError::Invalid(
+                "limits.max_level_size",
+                "must be greater than 0".into(),
+            ));
+        }
+        if self.limits.max_save_size == 0 {
+            return Err(ConfigError::Invalid(
+                "limits.max_save_size",
+                "must be greater than 0".into(),
+            ));
+        }
+        if self.limits.save_history == 0 {
+            return Err(ConfigError::Invalid(
+                "limits.save_history",
+                "must be at least 1".into(),
+            ));
+        }
+        if self.limits.login_attempts_per_account == 0 {
+            return Err(ConfigError::Invalid(
+                "limits.login_attempts_per_account",
+                "must be at least 1".into(),
+            ));
+        }
+        if self.limits.login_attempts_per_ip == 0 {
+            return Err(ConfigError::Invalid(
+                "limits.login_attempts_per_ip",
+                "must be at least 1".into(),
+            ));
+        }
+        if self.limits.login_lockout < 0 {
+            return Err(ConfigError::Invalid(
+                "limits.login_lockout",
+                "can't be negative".into(),
+            ));
+        }
+        if self.limits.username_change_interval < 0 {
+            return Err(ConfigError::Invalid(
+                "limits.username_change_interval",
+                "can't be negative".into(),
+            ));
+        }
+        if self.limits.account_deletion_delay < 0 {
+            return Err(ConfigError::Invalid(
+                "limits.account_deletion_delay",
+                "can't be negative".into(),
+            ));
+        }
+        if self.secret_key.is_empty() {
+            return Err(ConfigError::Invalid("secret_key", "must be set".into()));
+        }
+        if PLACEHOLDER_SECRET_KEYS.contains(&self.secret_key.trim().to_lowercase().as_str()) {
+            return Err(ConfigError::Invalid(
+                "secret_key",
+                "is still a placeholder, set it to something random".into(),
+            ));
+        }
+        if self.secret_key.len() < MIN_SECRET_KEY_LENGTH {
+            return Err(ConfigError::Invalid(
+                "secret_key",
+                format!("must be at least {MIN_SECRET_KEY_LENGTH} bytes long"),
+            ));
+        }
+        if self.mail.from.parse::<lettre::message::Mailbox>().is_err() {
+            return Err(ConfigError::Invalid(
+                "mail.from",
+                format!("'{}' isn't a valid address", self.mail.from),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Creates the storage directories, so a missing or unwritable path fails at startup
+    pub fn prepare_storage(&self) -> Result<(), ConfigError> {
+        if self.storage.backend == StorageBackendKind::Filesystem {
+            for (key, path) in [
+                ("storage.levels", self.storage.levels.clone()),
+                ("storage.levels", self.storage.levels.join("deleted")),
+                ("storage.saves", self.storage.saves.clone()),
+            ] {
+                fs::create_dir_all(&path).map_err(|e| {
+                    ConfigError::Invalid(key, format!("can't create '{}': {e}", path.display()))
+                })?;
+            }
+        }
+
+        if self.mail.transport == MailTransportKind::File {
+            fs::create_dir_all(&self.mail.directory).map_err(|e| {
+                ConfigError::Invalid(
+                    "mail.directory",
+                    format!("can't create '{}': {e}", self.mail.directory.display()),
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn override_from_env<T>(value: &mut T, key: &'static str) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let Ok(raw) = env::var(key) else {
+        return Ok(());
+    };
+
+    *value = raw
+        .parse()
+        .map_err(|e| ConfigError::Invalid(key, format!("'{raw}': {e}")))?;
+
+    Ok(())
+}
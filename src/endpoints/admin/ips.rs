@@ -0,0 +1,126 @@
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    extractors::auth::Authenticated,
+    middleware::ip_ban::{ClientIp, IpBanList},
+    types::{database::ModActionType, error::Result, response::CommonResponse},
+    utilities::{self, parse_network},
+};
+
+use super::authorize;
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct IpBanRequest {
+    // Either a single address or a CIDR range
+    ip: String,
+}
+
+async fn ban_ip(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Extension(bans): Extension<IpBanList>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<IpBanRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let Some(network) = parse_network(&data.ip) else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
+    let network = network.to_string();
+
+    let exists = sqlx::query_scalar!("SELECT count(*) FROM banned_ips WHERE ip = $1", network)
+        .fetch_one(&db)
+        .await?
+        .unwrap_or_default();
+
+    if exists != 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    sqlx::query!("INSERT INTO banned_ips (ip) VALUES ($1)", network)
+        .execute(&db)
+        .await?;
+
+    bans.reload(&db).await?;
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::IpBan,
+        &network,
+        "",
+        1,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+async fn unban_ip(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Extension(bans): Extension<IpBanList>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<IpBanRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let Some(network) = parse_network(&data.ip) else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
+    let network = network.to_string();
+
+    let result = sqlx::query!("DELETE FROM banned_ips WHERE ip = $1", network)
+        .execute(&db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    bans.reload(&db).await?;
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::IpBan,
+        &network,
+        "",
+        0,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+pub fn init() -> Router {
+    Router::new()
+        .route("/admin/ips/ban", post(ban_ip))
+        .route("/admin/ips/unban", post(unban_ip))
+}
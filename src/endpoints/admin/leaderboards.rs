@@ -0,0 +1,143 @@
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    extractors::auth::Authenticated,
+    middleware::ip_ban::ClientIp,
+    types::{
+        database::{BanScope, ModActionType},
+        error::Result,
+        response::CommonResponse,
+    },
+    utilities,
+};
+
+use super::authorize;
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct LeaderboardBanRequest {
+    #[serde(rename = "targetAccountID")]
+    target_account_id: i32,
+    #[serde(default)]
+    reason: String,
+    // Length of the ban in seconds, the ban is permanent if it's not set
+    duration: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct LeaderboardUnbanRequest {
+    #[serde(rename = "targetAccountID")]
+    target_account_id: i32,
+}
+
+async fn ban_from_leaderboards(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<LeaderboardBanRequest>,
+) -> Result<Response> {
+    let role = match authorize(&db, &account, ip).await? {
+        Some(role) => role,
+        None => {
+            return Ok(CommonResponse::InvalidRequest.into_response());
+        }
+    };
+
+    if role.tool_leaderboardsban != 1 || data.reason.len() > 255 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if utilities::database::get_account_by_id(&db, data.target_account_id)
+        .await?
+        .is_none()
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let expires_at = match data.duration {
+        Some(duration) if duration > 0 => Some(chrono::Utc::now().timestamp() + duration),
+        Some(_) => {
+            return Ok(CommonResponse::InvalidRequest.into_response());
+        }
+        None => None,
+    };
+
+    utilities::database::ban_account(
+        &db,
+        data.target_account_id,
+        BanScope::Leaderboard,
+        &data.reason,
+        expires_at,
+        account.account_id,
+    )
+    .await?;
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::LeaderboardBan,
+        &data.target_account_id.to_string(),
+        &data.reason,
+        1,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+async fn unban_from_leaderboards(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<LeaderboardUnbanRequest>,
+) -> Result<Response> {
+    let role = match authorize(&db, &account, ip).await? {
+        Some(role) => role,
+        None => {
+            return Ok(CommonResponse::InvalidRequest.into_response());
+        }
+    };
+
+    if role.tool_leaderboardsban != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let revoked =
+        utilities::database::unban_account(&db, data.target_account_id, BanScope::Leaderboard)
+            .await?;
+
+    if revoked == 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::LeaderboardBan,
+        &data.target_account_id.to_string(),
+        "",
+        0,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+pub fn init() -> Router {
+    Router::new()
+        .route("/admin/leaderboards/ban", post(ban_from_leaderboards))
+        .route("/admin/leaderboards/unban", post(unban_from_leaderboards))
+}
@@ -0,0 +1,23 @@
+pub mod ips;
+pub mod leaderboards;
+pub mod mod_ips;
+pub mod roles;
+pub mod saves;
+
+use std::net::IpAddr;
+
+use sqlx::PgPool;
+
+use crate::{
+    types::{
+        database::{Account, Role},
+        error::Result,
+    },
+    utilities,
+};
+
+// Admin endpoints take the same credentials game requests do (see `Authenticated`), on top
+// of that the moderator's role has to be usable from the address the request comes from.
+async fn authorize(db: &PgPool, account: &Account, ip: IpAddr) -> Result<Option<Role>> {
+    Ok(utilities::database::get_privileged_role(db, account.account_id, ip).await?)
+}
@@ -0,0 +1,139 @@
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    extractors::auth::Authenticated,
+    middleware::ip_ban::ClientIp,
+    types::{database::ModActionType, error::Result, response::CommonResponse},
+    utilities::{self, parse_network},
+};
+
+use super::authorize;
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct AddModIpRequest {
+    #[serde(rename = "targetAccountID")]
+    target_account_id: i32,
+    // Either a single address or a CIDR range
+    ip: String,
+    // `category_id` from 'mod_ip_perms', has to match the `modip_category` of the moderator's role
+    category: i32,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct RemoveModIpRequest {
+    #[serde(rename = "targetAccountID")]
+    target_account_id: i32,
+    ip: String,
+}
+
+async fn add_mod_ip(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<AddModIpRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let Some(network) = parse_network(&data.ip) else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
+    let network = network.to_string();
+
+    if utilities::database::get_account_by_id(&db, data.target_account_id)
+        .await?
+        .is_none()
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    sqlx::query!(
+        "INSERT INTO mod_ips (ip, is_mod, account_id, modip_category) VALUES ($1, 1, $2, $3)",
+        network,
+        data.target_account_id,
+        data.category
+    )
+    .execute(&db)
+    .await?;
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::ModIp,
+        &data.target_account_id.to_string(),
+        &network,
+        1,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+async fn remove_mod_ip(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<RemoveModIpRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let Some(network) = parse_network(&data.ip) else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
+    let network = network.to_string();
+
+    let result = sqlx::query!(
+        "DELETE FROM mod_ips WHERE ip = $1 AND account_id = $2",
+        network,
+        data.target_account_id
+    )
+    .execute(&db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::ModIp,
+        &data.target_account_id.to_string(),
+        &network,
+        0,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+pub fn init() -> Router {
+    Router::new()
+        .route("/admin/modips/add", post(add_mod_ip))
+        .route("/admin/modips/remove", post(remove_mod_ip))
+}
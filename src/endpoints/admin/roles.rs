@@ -0,0 +1,273 @@
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::{
+    extractors::auth::Authenticated,
+    middleware::ip_ban::ClientIp,
+    types::{database::ModActionType, error::Result, response::CommonResponse},
+    utilities,
+};
+
+use super::authorize;
+
+// Permission columns of 'roles' that can be granted through `permissions`
+const ROLE_PERMISSIONS: &[&str] = &[
+    "command_rate",
+    "command_feature",
+    "command_epic",
+    "command_unepic",
+    "command_verifycoins",
+    "command_daily",
+    "command_weekly",
+    "command_delete",
+    "command_setacc",
+    "command_rename_own",
+    "command_rename_all",
+    "command_pass_own",
+    "command_pass_all",
+    "command_description_own",
+    "command_description_all",
+    "command_public_own",
+    "command_public_all",
+    "command_unlist_own",
+    "command_unlist_all",
+    "command_sharecp_own",
+    "command_sharecp_all",
+    "command_song_own",
+    "command_song_all",
+    "profilecommand_discord",
+    "action_rate_demon",
+    "action_rate_stars",
+    "action_rate_difficulty",
+    "action_request_mod",
+    "action_suggest_rating",
+    "action_delete_comment",
+    "tool_leaderboardsban",
+    "tool_packcreate",
+    "tool_quests_create",
+    "tool_modactions",
+    "tool_suggestlist",
+    "dashboard_mod_tools",
+];
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct CreateRoleRequest {
+    #[serde(rename = "roleName")]
+    role_name: String,
+    #[serde(default)]
+    priority: i32,
+    // None(0), Moderator(1), Elder Moderator(2)
+    #[serde(default, rename = "badge")]
+    mod_badge_level: i32,
+    #[serde(default = "default_comment_color", rename = "commentColor")]
+    comment_color: String,
+    #[serde(default, rename = "modipCategory")]
+    modip_category: i32,
+    #[serde(default, rename = "isDefault")]
+    is_default: i32,
+    // Comma separated list of columns from `ROLE_PERMISSIONS`, everything else is denied
+    #[serde(default)]
+    permissions: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct AssignRoleRequest {
+    #[serde(rename = "targetAccountID")]
+    target_account_id: i32,
+    #[serde(rename = "roleID")]
+    role_id: i64,
+}
+
+fn default_comment_color() -> String {
+    "255,255,255".into()
+}
+
+fn is_valid_color(color: &str) -> bool {
+    let channels = color.split(',').collect::<Vec<_>>();
+
+    channels.len() == 3 && channels.iter().all(|channel| channel.parse::<u8>().is_ok())
+}
+
+async fn create_role(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<CreateRoleRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if data.role_name.is_empty()
+        || data.role_name.len() > 255
+        || !(0..=2).contains(&data.mod_badge_level)
+        || !is_valid_color(&data.comment_color)
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let granted = data
+        .permissions
+        .split_terminator(',')
+        .map(str::trim)
+        .collect::<Vec<_>>();
+
+    if granted
+        .iter()
+        .any(|permission| !ROLE_PERMISSIONS.contains(permission))
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "INSERT INTO roles (role_name, priority, mod_badge_level, comment_color, modip_category, is_default",
+    );
+    for permission in ROLE_PERMISSIONS {
+        query.push(", ").push(permission);
+    }
+    query.push(") VALUES (");
+
+    let mut values = query.separated(", ");
+    values
+        .push_bind(&data.role_name)
+        .push_bind(data.priority)
+        .push_bind(data.mod_badge_level)
+        .push_bind(&data.comment_color)
+        .push_bind(data.modip_category)
+        .push_bind((data.is_default != 0) as i32);
+    for permission in ROLE_PERMISSIONS {
+        values.push_bind(granted.contains(permission) as i32);
+    }
+    query.push(") RETURNING role_id");
+
+    let (role_id,): (i64,) = query.build_query_as().fetch_one(&db).await?;
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::RoleCreate,
+        &role_id.to_string(),
+        &data.role_name,
+        1,
+    )
+    .await?;
+
+    Ok(role_id.to_string().into_response())
+}
+
+async fn assign_role(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<AssignRoleRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if utilities::database::get_account_by_id(&db, data.target_account_id)
+        .await?
+        .is_none()
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO role_assign (role_id, account_id)
+        SELECT role_id, $2 FROM roles
+            WHERE role_id = $1
+              AND NOT EXISTS (SELECT 1 FROM role_assign WHERE role_id = $1 AND account_id = $2)
+    "#,
+        data.role_id,
+        data.target_account_id as i64
+    )
+    .execute(&db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::RoleAssign,
+        &data.target_account_id.to_string(),
+        &data.role_id.to_string(),
+        1,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+async fn revoke_role(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<AssignRoleRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let result = sqlx::query!(
+        "DELETE FROM role_assign WHERE role_id = $1 AND account_id = $2",
+        data.role_id,
+        data.target_account_id as i64
+    )
+    .execute(&db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::RoleAssign,
+        &data.target_account_id.to_string(),
+        &data.role_id.to_string(),
+        0,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+pub fn init() -> Router {
+    Router::new()
+        .route("/admin/roles/create", post(create_role))
+        .route("/admin/roles/assign", post(assign_role))
+        .route("/admin/roles/revoke", post(revoke_role))
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    config::Config,
+    extractors::auth::Authenticated,
+    middleware::ip_ban::ClientIp,
+    types::{
+        database::ModActionType, error::Result, objects::SaveVersionObject,
+        response::CommonResponse,
+    },
+    utilities::{self, storage::Storage, wire},
+};
+
+use super::authorize;
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct ListSavesRequest {
+    #[serde(rename = "targetAccountID")]
+    target_account_id: i32,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct RestoreSaveRequest {
+    #[serde(rename = "targetAccountID")]
+    target_account_id: i32,
+    #[serde(rename = "versionID")]
+    version_id: i32,
+}
+
+// Newest first, the first one is what the game gets when syncing
+async fn list_saves(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<ListSavesRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let versions = utilities::database::get_save_versions(&db, data.target_account_id).await?;
+    if versions.is_empty() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let versions = versions
+        .iter()
+        .map(SaveVersionObject::from)
+        .collect::<Vec<_>>();
+
+    Ok(wire::encode_list(&versions).into_response())
+}
+
+async fn restore_save(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(storage): Extension<Storage>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<RestoreSaveRequest>,
+) -> Result<Response> {
+    if authorize(&db, &account, ip).await?.is_none() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if account.is_admin != 1 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    if !utilities::saves::restore(
+        &db,
+        &storage,
+        &config,
+        data.target_account_id,
+        data.version_id,
+    )
+    .await?
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    utilities::database::log_mod_action(
+        &db,
+        account.account_id,
+        ModActionType::SaveRestore,
+        &data.target_account_id.to_string(),
+        &data.version_id.to_string(),
+        0,
+    )
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+pub fn init() -> Router {
+    Router::new()
+        .route("/admin/saves/list", post(list_saves))
+        .route("/admin/saves/restore", post(restore_save))
+}
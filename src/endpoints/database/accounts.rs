@@ -1,23 +1,33 @@
-use std::io::Read;
+use std::sync::Arc;
 
 use axum::{
     Extension, Form, Router,
     response::{IntoResponse, Response},
-    routing::post,
 };
-use flate2::read::GzDecoder;
+use lettre::Address;
 use serde::Deserialize;
 use sqlx::postgres::PgPool;
-use tokio::fs::{read_to_string, write};
 use tracing::error;
 
 use crate::{
-    types::response::{BackupResponse, CommonResponse, LoginResponse, RegisterResponse},
-    utilities,
+    config::Config,
+    endpoints::web,
+    extractors::auth::{AuthRejection, Authenticated},
+    middleware::client::{Secret, post},
+    middleware::ip_ban::ClientIp,
+    types::{
+        database::{ActionType, BanScope},
+        error::Result,
+        response::{BackupResponse, CommonResponse, LoginResponse, RegisterResponse},
+    },
+    utilities::{
+        self, credentials,
+        game_save::SaveStats,
+        mail::{Mail, MailError, Mailer},
+        storage::Storage,
+    },
 };
 
-use super::ACCOUNT_SECRET;
-
 #[derive(Deserialize)]
 #[allow(unused)]
 struct LoginRequest {
@@ -71,10 +81,6 @@ struct BackupRequest {
     id: String,
     #[serde(rename = "uuid")]
     user_id: String,
-    #[serde(rename = "accountID")]
-    account_id: i32,
-    #[serde(rename = "gjp2")]
-    hash: String,
     #[serde(rename = "saveData")]
     save_data: String,
     secret: String,
@@ -91,54 +97,66 @@ struct SyncRequest {
     id: String,
     #[serde(rename = "uuid")]
     user_id: String,
-    #[serde(rename = "accountID")]
-    account_id: i32,
-    #[serde(rename = "gjp2")]
-    hash: String,
     secret: String,
 }
 
 async fn register_account(
     Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(mailer): Extension<Mailer>,
     Form(data): Form<RegisterRequest>,
-) -> Response {
-    if data.secret != ACCOUNT_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
+) -> Result<Response> {
+    if data.username.len() < credentials::USERNAME_MIN_LENGTH {
+        return Ok(RegisterResponse::UsernameIsTooShort.into_response());
+    }
+    if data.password.len() < credentials::PASSWORD_MIN_LENGTH {
+        return Ok(RegisterResponse::PasswordIsTooShort.into_response());
     }
 
-    if data.username.len() < 3 {
-        return RegisterResponse::UsernameIsTooShort.into_response();
+    if data.username.len() > credentials::USERNAME_MAX_LENGTH {
+        return Ok(RegisterResponse::InvalidUsername.into_response());
     }
-    if data.password.len() < 6 {
-        return RegisterResponse::PasswordIsTooShort.into_response();
+    if data.email.parse::<Address>().is_err() {
+        return Ok(RegisterResponse::InvalidEmail.into_response());
     }
 
-    if data.username.len() > 20 {
-        return RegisterResponse::InvalidUsername.into_response();
+    // No account has ID 0, so this compares against all of them
+    if utilities::database::is_username_taken(&db, &data.username, 0).await? {
+        return Ok(RegisterResponse::AccountExists.into_response());
     }
-
-    let account = utilities::database::get_account_by_username(&db, &data.username).await;
+    let account = utilities::database::get_account_by_email(&db, &data.email).await?;
     if account.is_some() {
-        return RegisterResponse::AccountExists.into_response();
+        return Ok(RegisterResponse::EmailOccupied.into_response());
     }
 
-    let password = utilities::crypto::hash_password(&data.password).await;
-    let gjp2 = utilities::crypto::sha1_salt(&data.password, "mI29fmAnxgTs");
+    let password = credentials::hash(&config.secret_key, &credentials::gjp2(&data.password)).await;
+    let verify_email = config.features.email_verification;
+
+    let mut tx = db.begin().await?;
 
     let account_result = sqlx::query!(
         r#"
-        INSERT INTO accounts (username, password, email, is_active, gjp2)
-        VALUES ($1, $2, $3, $4, $5) RETURNING account_id
+        INSERT INTO accounts (username, password, salt, email, is_active, gjp2, register_date)
+        VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING account_id
     "#,
         data.username,
-        password,
+        password.hash,
+        password.salt,
         data.email,
-        true,
-        gjp2
+        !verify_email,
+        password.session,
+        chrono::Utc::now().timestamp() as i32,
     )
-    .fetch_one(&db)
-    .await
-    .unwrap();
+    .fetch_one(&mut *tx)
+    .await;
+    let account_result = match account_result {
+        Ok(account_result) => account_result,
+        // Someone else took the name between the check and the insert
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Ok(RegisterResponse::AccountExists.into_response());
+        }
+        Err(e) => return Err(e.into()),
+    };
 
     sqlx::query!(
         r#"
@@ -150,172 +168,264 @@ async fn register_account(
         data.username,
         chrono::Utc::now().timestamp() as i32,
     )
-    .execute(&db)
-    .await
-    .unwrap();
+    .execute(&mut *tx)
+    .await?;
 
     sqlx::query!(
         r#"
         INSERT INTO role_assign (role_id, account_id)
-        VALUES (3, $1)
+        SELECT role_id, $1 FROM roles WHERE is_default = 1
         "#,
         account_result.account_id as i64
     )
-    .execute(&db)
-    .await
-    .unwrap();
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    // Sent after committing, the mail server can take a while to answer. The account can't log
+    // in until it's activated, so if the mail doesn't go out it's removed again and the name and
+    // email are free to register with.
+    if verify_email
+        && let Err(e) = send_activation_mail(
+            &mailer,
+            &config,
+            account_result.account_id,
+            &data.username,
+            &data.email,
+        )
+        .await
+    {
+        error!("couldn't send activation mail to {}: {e}", data.email);
+
+        let mut tx = db.begin().await?;
+        sqlx::query!(
+            "DELETE FROM role_assign WHERE account_id = $1",
+            account_result.account_id as i64
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM users WHERE ext_id = $1 AND is_registered = 1",
+            account_result.account_id.to_string()
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM accounts WHERE account_id = $1",
+            account_result.account_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        return Ok(RegisterResponse::InvalidRequest.into_response());
+    }
 
-    RegisterResponse::Success.into_response()
+    Ok(RegisterResponse::Success.into_response())
+}
+
+async fn send_activation_mail(
+    mailer: &Mailer,
+    config: &Config,
+    account_id: i32,
+    username: &str,
+    email: &str,
+) -> Result<(), MailError> {
+    mailer
+        .send(Mail {
+            to: email.to_string(),
+            subject: "Activate your account".into(),
+            body: format!(
+                "Hi {username},\n\nopen this link to activate your account:\n{}\n\nIt stops working in two days, logging in from the game sends a new one.\n",
+                web::accounts::activation_link(config, account_id)
+            ),
+        })
+        .await
 }
 
 async fn login_account(
     Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
     Form(data): Form<LoginRequest>,
-) -> Response {
-    if data.secret != ACCOUNT_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
+) -> Result<Response> {
+    let account = utilities::database::get_account_by_username(&db, &data.username).await?;
+
+    let account_id = account.as_ref().map(|account| account.account_id);
+    if credentials::login_wait(&db, &config.limits, account_id, ip).await? > 0 {
+        return Ok(LoginResponse::TooManyAttempts.into_response());
     }
 
-    let account = match utilities::database::get_account_by_username(&db, &data.username).await {
-        Some(account) => account,
-        None => {
-            return LoginResponse::InvalidRequest.into_response();
-        }
+    let Some(account) = account else {
+        utilities::database::log_action(&db, 0, ActionType::FailedLogin, &data.username, ip)
+            .await?;
+        return Ok(LoginResponse::InvalidRequest.into_response());
     };
 
-    if !account.is_active {
-        return LoginResponse::AccountIsNotActivated.into_response();
+    if !credentials::check_login(&db, &config.secret_key, &account, &data.hash).await? {
+        utilities::database::log_action(
+            &db,
+            account.account_id,
+            ActionType::FailedLogin,
+            &account.username,
+            ip,
+        )
+        .await?;
+        return Ok(LoginResponse::WrongCredentials.into_response());
     }
-    if data.hash != account.gjp2.unwrap_or_default() {
-        return LoginResponse::WrongCredentials.into_response();
+
+    // Checked after the password, so the account's state isn't given away to anyone
+    if !account.is_active {
+        if config.features.email_verification
+            && let Err(e) = send_activation_mail(
+                &mailer,
+                &config,
+                account.account_id,
+                &account.username,
+                &account.email,
+            )
+            .await
+        {
+            error!("couldn't send activation mail to {}: {e}", account.email);
+        }
+
+        return Ok(LoginResponse::AccountIsNotActivated.into_response());
     }
 
-    let user = match utilities::database::get_user_by_id(&db, account.account_id).await {
+    utilities::database::log_action(
+        &db,
+        account.account_id,
+        ActionType::Login,
+        &account.username,
+        ip,
+    )
+    .await?;
+
+    let user = match utilities::database::get_user_by_account_id(&db, account.account_id).await? {
         Some(user) => user,
         None => {
-            return LoginResponse::InvalidRequest.into_response();
+            return Ok(LoginResponse::InvalidRequest.into_response());
         }
     };
 
-    if user.is_banned == 1 {
-        return LoginResponse::AccountIsBanned.into_response();
+    if utilities::database::get_active_ban(&db, account.account_id, BanScope::Login)
+        .await?
+        .is_some()
+    {
+        return Ok(LoginResponse::AccountIsBanned.into_response());
     }
 
-    format!("{},{}", account.account_id, user.user_id).into_response()
+    Ok(format!("{},{}", account.account_id, user.user_id).into_response())
 }
 
-async fn get_account_url() -> Response {
-    "https://rustyserver.local".into_response()
+async fn get_account_url(Extension(config): Extension<Arc<Config>>) -> Result<Response> {
+    Ok(config.public_url.clone().into_response())
 }
 
 async fn backup_account(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<BackupRequest>,
-) -> Response {
-    if data.secret != ACCOUNT_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
+    Extension(config): Extension<Arc<Config>>,
+    Extension(storage): Extension<Storage>,
+    request: Result<Authenticated<BackupRequest>, AuthRejection>,
+) -> Result<Response> {
+    let Authenticated {
+        account,
+        form: data,
+        ..
+    } = match request {
+        Ok(request) => request,
+        Err(AuthRejection::WrongCredentials) => {
+            return Ok(BackupResponse::WrongCredentials.into_response());
         }
+        Err(rejection) => return Ok(rejection.into_response()),
     };
 
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return BackupResponse::WrongCredentials.into_response();
+    if data.save_data.len() > config.limits.max_save_size {
+        return Ok(BackupResponse::TooLarge.into_response());
     }
 
-    let mut save_data = data.save_data.splitn(2, ";");
-    let compressed_data = save_data.next().unwrap();
-
-    let save_data = utilities::crypto::decode_base64_url_raw(compressed_data);
-
-    let mut decoder = GzDecoder::new(save_data.as_slice());
-    let mut decompressed_data = String::new();
-
-    match decoder.read_to_string(&mut decompressed_data) {
-        Ok(_) => (),
-        Err(e) => {
-            error!("{e:?}");
-            return BackupResponse::SomethingWentWrong.into_response();
-        }
-    };
-
-    let orbs = decompressed_data
-        .split("</s><k>14</k><s>")
-        .nth(1)
-        .and_then(|part| part.split("</s>").next())
-        .unwrap_or("0")
-        .parse::<i32>()
-        .unwrap();
-    let levels = decompressed_data
-        .split("<k>GS_value</k>")
-        .nth(1)
-        .and_then(|part| part.split("</s><k>4</k><s>").nth(1))
-        .and_then(|part| part.split("</s>").next())
-        .unwrap_or("0")
-        .parse::<i32>()
-        .unwrap();
-
-    sqlx::query!(
-        "UPDATE users SET orbs = $1, completed_lvls = $2 WHERE ext_id = $3",
-        orbs,
-        levels,
-        &data.account_id.to_string()
+    // Parsing a big save takes a while. One that can't be read is still kept, the game is the
+    // only thing that has to understand it, it just comes without stats.
+    let (save_data, stats) = tokio::task::spawn_blocking(move || {
+        let stats = SaveStats::from_save_data(&data.save_data);
+        (data.save_data, stats)
+    })
+    .await
+    .map_err(std::io::Error::other)?;
+    let stats = stats
+        .inspect_err(|e| error!("unreadable save from account {}: {e}", account.account_id))
+        .ok();
+
+    if let Err(e) = utilities::saves::store(
+        &db,
+        &storage,
+        &config,
+        account.account_id,
+        &save_data,
+        &stats.clone().unwrap_or_default(),
     )
-    .execute(&db)
     .await
-    .unwrap();
-
-    match write(format!("data/saves/{}", data.account_id), data.save_data).await {
-        Ok(_) => CommonResponse::Success.into_response(),
-        Err(_) => BackupResponse::InvalidRequest.into_response(),
+    {
+        error!("{e}");
+        return Ok(BackupResponse::InvalidRequest.into_response());
     }
-}
 
-async fn sync_account(Extension(db): Extension<PgPool>, Form(data): Form<SyncRequest>) -> Response {
-    if data.secret != ACCOUNT_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
+    // Only once the save is kept, the profile shouldn't show progress from a failed backup
+    if let Some(stats) = &stats {
+        utilities::database::update_save_stats(&db, account.account_id, stats).await?;
     }
 
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
+    Ok(CommonResponse::Success.into_response())
+}
+
+async fn sync_account(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(storage): Extension<Storage>,
+    request: Result<Authenticated<SyncRequest>, AuthRejection>,
+) -> Result<Response> {
+    let Authenticated { account, .. } = match request {
+        Ok(request) => request,
+        Err(AuthRejection::WrongCredentials) => {
+            return Ok(BackupResponse::WrongCredentials.into_response());
         }
+        Err(rejection) => return Ok(rejection.into_response()),
     };
 
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return BackupResponse::WrongCredentials.into_response();
-    }
-
-    let save_data = match read_to_string(format!("data/saves/{}", data.account_id)).await {
-        Ok(s) => s,
+    let save_data = match utilities::saves::load(&db, &storage, &config, account.account_id).await {
+        Ok(Some(save_data)) => save_data,
+        Ok(None) => return Ok(CommonResponse::InvalidRequest.into_response()),
         Err(e) => {
-            error!("{e:?}");
-            return CommonResponse::InvalidRequest.into_response();
+            error!("{e}");
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    format!("{};21;30;a;a", save_data).into_response()
+    Ok(format!("{};21;30;a;a", save_data).into_response())
 }
 
 pub fn init() -> Router {
     Router::new()
-        .route("/database/accounts/loginGJAccount.php", post(login_account))
+        .route(
+            "/database/accounts/loginGJAccount.php",
+            post(Secret::Account, login_account),
+        )
         .route(
             "/database/accounts/registerGJAccount.php",
-            post(register_account),
+            post(Secret::Account, register_account),
         )
         .route(
             "/database/accounts/backupGJAccountNew.php",
-            post(backup_account),
+            post(Secret::Account, backup_account),
         )
         .route(
             "/database/accounts/syncGJAccountNew.php",
-            post(sync_account),
+            post(Secret::Account, sync_account),
+        )
+        .route(
+            "/database/getAccountURL.php",
+            post(Secret::Common, get_account_url),
         )
-        .route("/database/getAccountURL.php", post(get_account_url))
 }
@@ -1,28 +1,36 @@
-use std::net::SocketAddr;
+use std::sync::Arc;
 
 use axum::{
     Extension, Router,
-    extract::ConnectInfo,
     response::{IntoResponse, Response},
-    routing::post,
 };
 use axum_extra::extract::Form;
 use serde::{Deserialize, Deserializer};
 use sha1::{Digest, Sha1};
 use sqlx::PgPool;
-use tokio::fs::{read, rename, try_exists, write};
 use tracing::error;
 
 use crate::{
+    config::Config,
+    extractors::auth::Authenticated,
+    middleware::client::{Secret, post},
+    middleware::ip_ban::ClientIp,
     types::{
-        database::{Level, LevelScore},
-        response::{CommonResponse, LevelUploadResponse},
+        database::{BanScope, Level, LevelScore},
+        error::Result,
+        objects::{CommentAuthor, LevelComment, LevelObject, LevelScoreUser, SongObject},
+        response::{CommentsResponse, CommonResponse, LevelUploadResponse},
+    },
+    utilities::{
+        self,
+        cache::LevelSearchCache,
+        credentials,
+        search::{self, Clause, LevelSearch},
+        storage::{Blob, Storage},
+        wire::{self, WireObject},
     },
-    utilities,
 };
 
-use super::{COMMON_SECRET, LEVEL_SECRET, MOD_SECRET};
-
 fn deserialize_enum_from_int<'de, D>(deserializer: D) -> Result<Option<QueryType>, D::Error>
 where
     D: Deserializer<'de>,
@@ -33,6 +41,7 @@ where
         1 => Ok(Some(QueryType::MostDownloaded)),
         2 => Ok(Some(QueryType::MostLiked)),
         3 => Ok(Some(QueryType::Trending)),
+        4 => Ok(Some(QueryType::Recent)),
         5 => Ok(Some(QueryType::LevelsPerUser)),
         6 => Ok(Some(QueryType::Featured)),
         7 => Ok(Some(QueryType::Magic)),
@@ -68,6 +77,7 @@ enum QueryType {
     MostDownloaded = 1,
     MostLiked = 2,
     Trending = 3,
+    Recent = 4,
     LevelsPerUser = 5,
     Featured = 6,
     Magic = 7,
@@ -86,6 +96,20 @@ enum QueryType {
     SentLevels = 27,
 }
 
+impl QueryType {
+    // The tabs everyone opens and that only change when a level is rated. Searches are mostly
+    // one-offs, caching them would only push these out.
+    fn is_cached(&self) -> bool {
+        matches!(
+            self,
+            QueryType::Featured
+                | QueryType::FeaturedGDW
+                | QueryType::HallOfFame
+                | QueryType::Awarded
+        )
+    }
+}
+
 #[derive(Deserialize, Debug, Default)]
 #[allow(unused)]
 struct UploadLevelRequest {
@@ -145,10 +169,6 @@ struct UploadLevelRequest {
     pub id: Option<String>,
     #[serde(rename = "uuid")]
     pub user_id: Option<i32>,
-    #[serde(rename = "accountID")]
-    pub account_id: Option<i32>,
-    #[serde(rename = "gjp2")]
-    pub hash: Option<String>,
     #[serde(rename = "userName")]
     pub username: Option<String>,
     #[serde(rename = "levelVersion")]
@@ -264,10 +284,6 @@ struct DeleteLevelRequest {
     pub id: String,
     #[serde(rename = "uuid")]
     pub user_id: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "levelID")]
     pub level_id: i32,
     pub secret: String,
@@ -283,10 +299,6 @@ struct SuggestStarsRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "levelID")]
     pub level_id: i32,
     pub stars: i32,
@@ -305,10 +317,6 @@ struct RateStarsRequest {
     pub id: String,
     #[serde(rename = "uuid")]
     pub user_id: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "levelID")]
     pub level_id: i32,
     pub stars: i32,
@@ -330,10 +338,6 @@ struct RateDemonRequest {
     pub id: String,
     #[serde(rename = "uuid")]
     pub user_id: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "levelID")]
     pub level_id: i32,
     pub rating: i32,
@@ -362,10 +366,6 @@ struct UpdateDescriptionRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "levelID")]
     pub level_id: i32,
     #[serde(rename = "levelDesc")]
@@ -383,10 +383,6 @@ struct GetLevelScoresRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "levelID")]
     pub level_id: i32,
     pub percent: i32,
@@ -432,10 +428,6 @@ struct UploadLevelCommentRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "userName")]
     pub username: String,
     pub comment: String,
@@ -480,30 +472,11 @@ struct DeleteLevelCommentRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "commentID")]
     pub comment_id: i32,
     pub secret: String,
     #[serde(rename = "levelID")]
-    pub level_id: i32
-}
-
-#[derive(sqlx::FromRow, Deserialize, Debug, Default)]
-#[allow(unused)]
-struct Song {
-    pub id: i32,
-    pub name: String,
-    pub author_id: i32,
-    pub author_name: String,
-    pub size: String,
-    pub download: String,
-    pub hash: String,
-    pub is_disabled: i32,
-    pub levels_count: i32,
-    pub reupload_time: i32,
+    pub level_id: i32,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -527,78 +500,64 @@ fn default_leaderboard_type() -> i32 {
 
 async fn upload_level(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<UploadLevelRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    if data.account_id.unwrap_or(0) == 0 || data.user_id.unwrap_or(0) == 0 {
-        return CommonResponse::InvalidRequest.into_response();
+    Extension(config): Extension<Arc<Config>>,
+    Extension(storage): Extension<Storage>,
+    Extension(cache): Extension<LevelSearchCache>,
+    Authenticated {
+        account,
+        user,
+        form: data,
+    }: Authenticated<UploadLevelRequest>,
+) -> Result<Response> {
+    if !config.features.level_uploads {
+        return Ok(LevelUploadResponse::UploadingDisabled.into_response());
     }
 
-    let account_id = data.account_id.unwrap();
-    let user_id = data.user_id.unwrap();
-
-    let account = match utilities::database::get_account_by_id(&db, account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if data.hash != account.gjp2 {
-        return CommonResponse::InvalidRequest.into_response();
+    if data.level_string.len() > config.limits.max_level_size {
+        return Ok(LevelUploadResponse::FailedToWriteLevel.into_response());
     }
 
     let level = sqlx::query!(
         "SELECT count(*) FROM levels WHERE level_id = $1 AND user_id = $2 AND is_deleted = 0",
         data.level_id,
-        user_id
+        user.user_id
     )
-    .fetch_optional(&db)
-    .await
-    .unwrap();
-
-    if level.unwrap().count.unwrap_or_default() != 0 {
-        let level_path = format!("./data/levels/{}", data.level_id);
+    .fetch_one(&db)
+    .await?;
 
-        let level_write_result = write(level_path, &data.level_string).await;
-        if level_write_result.is_err() {
-            return LevelUploadResponse::FailedToWriteLevel.into_response();
+    if level.count.unwrap_or_default() != 0 {
+        if let Err(e) = storage
+            .put(&Blob::Level(data.level_id), data.level_string.as_bytes())
+            .await
+        {
+            error!("{e}");
+            return Ok(LevelUploadResponse::FailedToWriteLevel.into_response());
         }
 
         sqlx::query!(r#"
             UPDATE levels
                 SET game_version = $1, binary_version = $2, level_desc = $3, level_version = level_version + 1, level_length = $4, audio_track = $5, auto = $6, original = $7,
-                    two_player = $8, song_id = $9, objects = $10, coins = $11, requested_stars = $12, extra_string = $13, level_string = $14, level_info = $15, unlisted = $16,
-                    is_ldm = $17, wt = $18, wt2 = $19, unlisted2 = $20, settings_string = $21, song_ids = $22, sfx_ids = $23, ts = $24, password = $25, update_date = $26
-            WHERE level_id = $27
+                    two_player = $8, song_id = $9, objects = $10, coins = $11, requested_stars = $12, extra_string = $13, level_string = NULL, level_info = $14, unlisted = $15,
+                    is_ldm = $16, wt = $17, wt2 = $18, unlisted2 = $19, settings_string = $20, song_ids = $21, sfx_ids = $22, ts = $23, password = $24, update_date = $25
+            WHERE level_id = $26
         "#, data.game_version, data.binary_version, data.level_description, data.level_length, data.audio_track, data.auto, data.original, data.two_player, data.song_id,
-            data.objects, data.coins, data.requested_stars, data.extra_string, data.level_string, data.level_info.unwrap_or_default(), data.unlisted, data.is_ldm, data.wt, data.wt2, 0,
+            data.objects, data.coins, data.requested_stars, data.extra_string, data.level_info.unwrap_or_default(), data.unlisted, data.is_ldm, data.wt, data.wt2, 0,
             data.settings_string, data.song_ids, data.sfx_ids, data.ts, data.password, chrono::Utc::now().timestamp() as i32, data.level_id)
             .execute(&db)
-            .await
-            .unwrap();
+            .await?;
+
+        cache.invalidate(&db).await;
 
-        return format!("{}", data.level_id).into_response();
+        return Ok(format!("{}", data.level_id).into_response());
     }
 
     let timestamp = chrono::Utc::now().timestamp();
 
-    let temporary_level_path = format!("./data/levels/{}_{}", user_id, timestamp);
-
-    let level_write_result = write(&temporary_level_path, &data.level_string).await;
-    if level_write_result.is_err() {
-        error!("{level_write_result:?}");
-        return LevelUploadResponse::FailedToWriteLevel.into_response();
-    }
-
     let level_insert = sqlx::query!(
         r#"
             INSERT INTO levels (
                 user_id, ext_id, username, game_version, binary_version, level_name, level_desc, level_version, level_length, audio_track,
-                auto, original, two_player, song_id, objects, coins, requested_stars, extra_string, level_string, level_info,
+                auto, original, two_player, song_id, objects, coins, requested_stars, extra_string, level_info,
                 secret, unlisted, is_ldm, wt, wt2, settings_string, song_ids, sfx_ids, ts, password,
                 upload_date, update_date, hostname
             )
@@ -606,330 +565,258 @@ async fn upload_level(
                 $1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
                 $11, $12, $13, $14, $15, $16, $17, $18, $19, $20,
                 $21, $22, $23, $24, $25, $26, $27, $28, $29, $30,
-                $31, $32, $33
+                $31, $32
             )
             RETURNING level_id
         "#,
-        data.user_id, data.account_id.map(|id| id.to_string()).unwrap_or_default(), data.username.as_deref().unwrap_or(""), data.game_version, data.binary_version,
+        user.user_id, account.account_id.to_string(), user.username, data.game_version, data.binary_version,
         data.level_name, data.level_description, 1, data.level_length, data.audio_track, data.auto, data.original,
-        data.two_player, data.song_id, data.objects, data.coins, data.requested_stars, data.extra_string, data.level_string, data.level_info.unwrap_or_default(),
+        data.two_player, data.song_id, data.objects, data.coins, data.requested_stars, data.extra_string, data.level_info.unwrap_or_default(),
         data.secret, data.unlisted, data.is_ldm, data.wt, data.wt2, data.settings_string, data.song_ids, data.sfx_ids,
         data.ts, data.password, timestamp, timestamp, ""
     )
     .fetch_one(&db)
-    .await
-    .unwrap();
+    .await?;
 
-    rename(
-        temporary_level_path,
-        format!("data/levels/{}", &level_insert.level_id),
-    )
-    .await
-    .unwrap();
+    // The row only counts once its data is there too
+    if let Err(e) = storage
+        .put(
+            &Blob::Level(level_insert.level_id),
+            data.level_string.as_bytes(),
+        )
+        .await
+    {
+        error!("{e}");
+        sqlx::query!(
+            "DELETE FROM levels WHERE level_id = $1",
+            level_insert.level_id
+        )
+        .execute(&db)
+        .await?;
+        return Ok(LevelUploadResponse::FailedToWriteLevel.into_response());
+    }
+
+    cache.invalidate(&db).await;
 
-    format!("{}", level_insert.level_id).into_response()
+    Ok(format!("{}", level_insert.level_id).into_response())
 }
 
 async fn get_levels(
     Extension(db): Extension<PgPool>,
+    Extension(cache): Extension<LevelSearchCache>,
     Form(data): Form<GetLevelsRequest>,
-) -> Response {
-    if data.secret.unwrap_or_default() != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+) -> Result<Response> {
     let user_id = data.user_id.unwrap_or_default();
     if user_id == 0 {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     let game_version = data.game_version;
     let page_offset = data.page.unwrap_or(0) * 10;
+    let account_id = data.account_id.unwrap_or_default().to_string();
+
+    let (Some(difficulty_filter), Some(length_filter), Some(followed), Some(completed_levels)) = (
+        search::parse_id_list(&data.difficulty_filter.join(",")),
+        search::parse_id_list(&data.length_filter),
+        search::parse_id_list(&data.followed.unwrap_or_default()),
+        search::parse_id_list(&data.completed_levels.unwrap_or_default()),
+    ) else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
 
-    let time = chrono::Utc::now().timestamp();
-    let mut order = String::from("upload_date");
-    let mut order_sorting = "DESC";
-    let mut query_to_join = "";
-    let mut no_limit = false;
-    let mut filters: Vec<String> = vec!["(unlisted = 0 AND unlisted2 = 0)".into()];
-
-    let difficulty_filter = data.difficulty_filter;
-    let length_filter = &data.length_filter.split(", ").collect::<Vec<_>>();
-
-    let followed = &data.followed.unwrap_or_default();
-    let followed = followed.split(", ").collect::<Vec<_>>();
-    let completed_levels = &data.completed_levels.unwrap_or_default();
-    let completed_levels = completed_levels.split(", ").collect::<Vec<_>>();
-
-    let version_filter = format!("levels.game_version <= '{}'", game_version);
-    filters.push(version_filter);
+    let mut search = LevelSearch::new(game_version, page_offset as i64);
 
     if data.original.unwrap_or_default() != 0 {
-        filters.push("original = 0".into());
+        search.original_only();
     }
     if data.coins.unwrap_or_default() != 0 {
-        filters.push("star_coins = 1 AND NOT levels.coins = 0".into());
+        search.coins_only();
     }
-    if (data.uncompleted.unwrap_or_default() != 0 || data.only_completed.unwrap_or_default() != 0)
-        && !completed_levels.is_empty()
-    {
-        let op = if data.uncompleted.unwrap_or_default() != 0 {
-            "NOT "
-        } else {
-            ""
-        };
-        filters.push(format!(
-            "{}level_id IN ({})",
-            op,
-            completed_levels.join(",")
-        ));
+    if data.uncompleted.unwrap_or_default() != 0 || data.only_completed.unwrap_or_default() != 0 {
+        search.completed(completed_levels, data.uncompleted.unwrap_or_default() != 0);
     }
-    if let Some(song) = data.song {
-        if song > 0 {
-            if data.custom_song.is_none() {
-                filters.push(format!("audio_track = '{}' AND song_id = 0", song - 1));
-            } else {
-                filters.push(format!("song_id = '{}'", song));
-            }
-        }
+    if let Some(song) = data.song.filter(|&song| song > 0) {
+        search.song(song, data.custom_song.is_some());
     }
     if data.two_player.unwrap_or_default() != 0 {
-        filters.push("two_player = 1".into());
+        search.two_player();
     }
     if data.star.unwrap_or_default() != 0 {
-        filters.push("NOT star_stars = 0".into());
+        search.starred();
     }
     if data.no_star.unwrap_or_default() != 0 {
-        filters.push("star_stars = 0".into());
-    }
-    if !length_filter.is_empty() && !length_filter.contains(&"-") {
-        filters.push(format!("level_length IN ({})", length_filter.join(",")));
-    }
-
-    let mut rating_filters: Vec<String> = Vec::new();
-    if data.featured.unwrap_or_default() != 0 {
-        rating_filters.push("star_featured > 0".into());
-    }
-    if data.epic.unwrap_or_default() != 0 {
-        rating_filters.push("star_epic = 1".into());
-    }
-    if data.mythic.unwrap_or_default() != 0 {
-        rating_filters.push("star_epic = 2".into());
-    }
-    if data.legendary.unwrap_or_default() != 0 {
-        rating_filters.push("star_epic = 3".into());
-    }
-    if !rating_filters.is_empty() {
-        filters.push(format!("({})", rating_filters.join(" OR ")));
-    }
-
-    if difficulty_filter.contains(&"-2".to_string()) {
-        filters.push("star_demon = 1".into());
-        match data.demon_filter.unwrap_or_default() {
-            1 => filters.push("star_demon_diff = '3'".into()),
-            2 => filters.push("star_demon_diff = '4'".into()),
-            3 => filters.push("star_demon_diff = '0'".into()),
-            4 => filters.push("star_demon_diff = '5'".into()),
-            5 => filters.push("star_demon_diff = '6'".into()),
-            _ => (),
-        };
-    } else if difficulty_filter.contains(&"-1".to_string()) {
-        filters.push("star_difficulty = '0'".into());
-    } else if difficulty_filter.contains(&"-3".to_string()) {
-        filters.push("star_auto = '1'".into());
-    } else if !difficulty_filter.is_empty() && !difficulty_filter.contains(&"-".to_string()) {
-        let diff_string = difficulty_filter
-            .iter()
-            .map(|d| format!("{}0", d))
-            .collect::<Vec<_>>()
-            .join(",");
-        filters.push(format!(
-            "star_difficulty IN ({}) AND star_auto = '0' AND star_demon = '0'",
-            diff_string
-        ));
+        search.unstarred();
     }
+    search.lengths(length_filter);
+    search.ratings(
+        data.featured.unwrap_or_default() != 0,
+        data.epic.unwrap_or_default() != 0,
+        data.legendary.unwrap_or_default() != 0,
+        data.mythic.unwrap_or_default() != 0,
+    );
+    search.difficulty(&difficulty_filter, data.demon_filter.unwrap_or_default());
 
     let query = data.query.unwrap_or_default();
     let query_type = data.query_type.unwrap_or(QueryType::Search);
+    let is_cached = query_type.is_cached();
 
     match query_type {
-        QueryType::Search | QueryType::MostLikedGDW => {
-            order = "likes".into();
-
-            if !query.is_empty() {
-                if query.parse::<i32>().is_ok() {
-                    let level_id = query.parse::<i32>().unwrap_or_default();
-                    filters = vec![format!(
-                        "level_id = {} AND (unlisted != 1 OR (unlisted = 1 AND (ext_id = '{}')))",
-                        level_id,
-                        data.account_id.unwrap_or_default()
-                    )];
-                } else {
-                    let first_char = query.chars().next().unwrap_or('d');
-                    match first_char {
-                        'u' => {
-                            let potential_user_id = query[1..].parse::<i32>();
-                            if let Ok(id) = potential_user_id {
-                                filters.push(format!("user_id = {}", id));
-                            } else {
-                                filters.push(format!("level_name ILIKE '%{}%'", query));
-                            }
-                        }
-                        'a' => {
-                            let potential_account_id = query[1..].parse::<i32>();
-                            if let Ok(id) = potential_account_id {
-                                filters.push(format!("ext_id = '{}'", id));
-                            } else {
-                                filters.push(format!("level_name ILIKE '%{}%'", query));
-                            }
-                        }
-                        _ => {
-                            filters.push(format!(
-                                "level_name ILIKE '%{}%'",
-                                query
-                            ));
-                        }
-                    }
-                }
+        QueryType::Search => {
+            search.order_by(Clause::new("likes"), true);
+
+            if let Ok(level_id) = query.parse::<i32>() {
+                search.replace_filters(
+                    Clause::new("levels.level_id = ")
+                        .bind(level_id)
+                        .sql(" AND (unlisted != 1 OR (unlisted = 1 AND ext_id = ")
+                        .bind(account_id)
+                        .sql("))"),
+                );
+            } else if let Some(Ok(id)) = query.strip_prefix('u').map(str::parse::<i32>) {
+                search.filter(Clause::new("levels.user_id = ").bind(id));
+            } else if let Some(Ok(id)) = query.strip_prefix('a').map(str::parse::<i32>) {
+                search.filter(Clause::new("ext_id = ").bind(id.to_string()));
+            } else if !query.is_empty() {
+                search.text(&query);
             }
         }
         QueryType::MostDownloaded => {
-            order = "downloads".into();
+            search.order_by(Clause::new("downloads"), true);
+        }
+        QueryType::MostLiked | QueryType::MostLikedGDW => {
+            search.order_by(Clause::new("likes"), true);
         }
-        QueryType::MostLiked => {
-            order = "likes".into();
+        QueryType::Trending => {
+            search.trending();
+        }
+        QueryType::Recent => {
+            search.order_by(Clause::new("upload_date"), true);
         }
         QueryType::LevelsPerUser => {
-            if data.user_id == query.parse::<i32>().ok() {
-                filters.clear();
-                filters.push("(unlisted = 0 AND unlisted2 = 0)".into());
-                let version_filter = format!("levels.game_version <= '{}'", game_version);
-                filters.push(version_filter);
+            let Ok(target_user_id) = query.parse::<i32>() else {
+                return Ok(CommonResponse::InvalidRequest.into_response());
+            };
+
+            if data.user_id == Some(target_user_id) {
+                search.reset_filters();
             }
-            filters.push(format!("levels.user_id = '{}'", query));
+            search.filter(Clause::new("levels.user_id = ").bind(target_user_id));
         }
         QueryType::Featured | QueryType::FeaturedGDW => {
             if game_version > 21 {
-                filters.push("(NOT star_featured = 0 OR NOT star_epic = 0)".into());
+                search.filter(Clause::new("NOT star_featured = 0 OR NOT star_epic = 0"));
             } else {
-                filters.push("NOT star_featured = 0".into());
+                search.filter(Clause::new("NOT star_featured = 0"));
             }
-            order = "star_featured DESC, rate_date DESC, upload_date".into();
+            search.order_by(
+                Clause::new("star_featured DESC, rate_date DESC, upload_date"),
+                true,
+            );
         }
         QueryType::HallOfFame => {
-            filters.push("NOT star_epic = 0".into());
-            order = "star_featured DESC, rate_date DESC, upload_date".into();
+            search.filter(Clause::new("NOT star_epic = 0"));
+            search.order_by(
+                Clause::new("star_featured DESC, rate_date DESC, upload_date"),
+                true,
+            );
         }
         QueryType::Magic => {
-            filters.push("level_desc ILIKE '%#magic%'".into());
-            filters.push("objects > 9999".into());
+            search.filter(Clause::new("level_desc ILIKE '%#magic%'"));
+            search.filter(Clause::new("objects > 9999"));
         }
         QueryType::MapPacks | QueryType::Unknown => {
-            if !query.is_empty() {
-                let levels_array: Vec<&str> = query.split(',').collect();
-                let mut levels_text = String::new();
-
-                for (i, level_id) in levels_array.iter().enumerate() {
-                    levels_text.push_str(&format!("WHEN level_id = {} THEN {} ", level_id, i + 1));
-                }
-
-                order = format!("CASE {}\nEND", levels_text);
-                order_sorting = "ASC";
-
-                filters.push(format!(
-                    "level_id IN ({}) AND (unlisted != 1 OR (unlisted = 1 AND (ext_id = '{}')))",
-                    query,
-                    data.account_id.unwrap_or_default()
-                ));
-
-                no_limit = true;
-            }
+            let Some(level_ids) = search::parse_id_list(&query) else {
+                return Ok(CommonResponse::InvalidRequest.into_response());
+            };
+
+            search.order_by(
+                Clause::new("array_position(")
+                    .bind(level_ids.clone())
+                    .sql(", levels.level_id)"),
+                false,
+            );
+            search.filter(
+                Clause::new("levels.level_id = ANY(")
+                    .bind(level_ids)
+                    .sql(") AND (unlisted != 1 OR (unlisted = 1 AND ext_id = ")
+                    .bind(account_id)
+                    .sql("))"),
+            );
+            search.without_limit();
         }
         QueryType::Awarded => {
-            filters.push("NOT star_stars = 0".into());
-            order = "rate_date DESC, upload_date".into();
+            search.filter(Clause::new("NOT star_stars = 0"));
+            search.order_by(Clause::new("rate_date DESC, upload_date"), true);
         }
         QueryType::Followed => {
-            if followed.is_empty() {
-                filters.push("1 != 1".into());
-            } else {
-                filters.push(format!("ext_id IN ({})", followed.join(", ")));
-            }
+            let followed = followed.iter().map(i32::to_string).collect::<Vec<_>>();
+            search.filter(Clause::new("ext_id = ANY(").bind(followed).sql(")"));
         }
         QueryType::Friends => {
-            filters.push(format!(
-                "ext_id = '{}'",
-                data.account_id.unwrap_or_default()
-            ));
+            search.filter(Clause::new("ext_id = ").bind(account_id));
         }
+        // The time is left to the database, a bound one would make every search a new cache key
         QueryType::DailySafe => {
-            query_to_join =
-                "INNER JOIN daily_features ON levels.level_id = daily_features.level_id";
-            filters.push(format!("daily_features.type = 0 AND timestamp < {}", time));
-            order = "daily_features.fea_id".into();
+            search.join("INNER JOIN daily_features ON levels.level_id = daily_features.level_id");
+            search.filter(Clause::new(
+                "daily_features.type = 0 AND timestamp < EXTRACT(EPOCH FROM NOW())",
+            ));
+            search.order_by(Clause::new("daily_features.fea_id"), true);
         }
         QueryType::WeeklySafe => {
-            query_to_join =
-                "INNER JOIN daily_features ON levels.level_id = daily_features.level_id";
-            filters.push(format!("daily_features.type = 1 AND timestamp < {}", time));
-            order = "daily_features.fea_id".into();
+            search.join("INNER JOIN daily_features ON levels.level_id = daily_features.level_id");
+            search.filter(Clause::new(
+                "daily_features.type = 1 AND timestamp < EXTRACT(EPOCH FROM NOW())",
+            ));
+            search.order_by(Clause::new("daily_features.fea_id"), true);
         }
         QueryType::EventSafe => {
-            query_to_join = "INNER JOIN events ON levels.level_id = events.level_id";
-            filters.push(format!("timestamp < {}", time));
-            order = "events.fea_id".into();
+            search.join("INNER JOIN events ON levels.level_id = events.level_id");
+            search.filter(Clause::new("timestamp < EXTRACT(EPOCH FROM NOW())"));
+            search.order_by(Clause::new("events.fea_id"), true);
         }
         QueryType::ListLevels => {
-            if !query.is_empty() {
-                filters = vec![format!(
-                    "level_id IN ({}) AND (unlisted != 1 OR (unlisted = 1 AND (ext_id = '{}')))",
-                    query,
-                    data.account_id.unwrap_or_default()
-                )];
-                no_limit = true;
-            }
+            let Some(level_ids) = search::parse_id_list(&query) else {
+                return Ok(CommonResponse::InvalidRequest.into_response());
+            };
+
+            search.replace_filters(
+                Clause::new("levels.level_id = ANY(")
+                    .bind(level_ids)
+                    .sql(") AND (unlisted != 1 OR (unlisted = 1 AND ext_id = ")
+                    .bind(account_id)
+                    .sql("))"),
+            );
+            search.without_limit();
         }
         QueryType::SentLevels => {
-            query_to_join = "JOIN (SELECT suggest_level_id as level_id, MAX(suggest.timestamp) AS timestamp FROM suggest GROUP BY level_id) suggest ON levels.level_id = suggest.level_id";
-            filters.push("suggest.level_id > 0".into());
-            order = "suggest.timestamp".into();
+            search.join("JOIN (SELECT suggest_level_id as level_id, MAX(suggest.timestamp) AS timestamp FROM suggest GROUP BY level_id) suggest ON levels.level_id = suggest.level_id");
+            search.filter(Clause::new("suggest.level_id > 0"));
+            search.order_by(Clause::new("suggest.timestamp"), true);
         }
-        _ => (),
     };
 
-    let limit_clause = if no_limit {
-        "".to_string()
-    } else {
-        format!("LIMIT 10 OFFSET {}", page_offset)
-    };
-
-    let end_query = format!(
-        "SELECT * FROM levels {} WHERE ({}) AND is_deleted = 0 ORDER BY {} {} {}",
-        query_to_join,
-        filters.join(") AND ("),
-        order,
-        order_sorting,
-        limit_clause
-    );
-    let count_query = format!(
-        "SELECT count(*) FROM levels {} WHERE ({}) AND is_deleted = 0",
-        query_to_join,
-        filters.join(") AND (")
-    );
+    if is_cached && let Some(response) = cache.get(&search, page_offset) {
+        return Ok(response.into_response());
+    }
 
-    let levels = sqlx::query_as::<_, Level>(&end_query)
+    let levels = search
+        .select_query()
+        .build_query_as::<Level>()
         .fetch_all(&db)
-        .await
-        .unwrap_or_default();
-    let levels_count: (i64,) = sqlx::query_as(&count_query)
-        .fetch_one(&db)
-        .await
-        .unwrap_or((0,));
+        .await?;
+    let levels_count: (i64,) = search.count_query().build_query_as().fetch_one(&db).await?;
+
+    let song_ids = levels
+        .iter()
+        .map(|level| level.song_id)
+        .filter(|&id| id != 0)
+        .collect::<Vec<_>>();
+    let songs = utilities::database::get_songs_by_ids(&db, &song_ids).await?;
 
     let mut level_stats: Vec<LevelStats> = Vec::new();
-    let mut result_string = String::new();
-    let mut user_string = String::new();
-    let mut song_string = String::new();
+    let mut level_objects = Vec::new();
+    let mut user_strings = Vec::new();
 
     for level in levels {
         level_stats.push(LevelStats {
@@ -938,69 +825,23 @@ async fn get_levels(
             stars: level.star_stars,
         });
 
-        let star_demon_str = if level.star_demon == 0 {
-            "17:"
-        } else {
-            &format!("17:{}", level.star_demon)
-        };
-        let star_auto_str = if level.star_auto == 0 {
-            "25:"
-        } else {
-            &format!("25:{}", level.star_auto)
-        };
-
-        result_string += &format!(
-            "1:{}:2:{}:5:{}:6:{}:8:{}:9:{}:10:{}:11:1:12:{}:13:{}:14:{}:{}:43:{}:{}:18:{}:19:{}:42:{}:45:{}:3:{}:15:{}:30:{}:31:{}:37:{}:38:{}:39:{}:46:{}:47:{}:35:{}|",
-            level.level_id,
-            level.level_name,
-            level.level_version,
-            level.user_id,
-            "10",
-            level.star_difficulty,
-            level.downloads,
-            level.audio_track,
-            level.game_version,
-            level.likes,
-            star_demon_str,
-            level.star_demon_diff,
-            star_auto_str,
-            level.star_stars,
-            level.star_featured,
-            level.star_epic,
-            level.objects,
-            utilities::crypto::decode_base64(&level.level_desc),
-            level.level_length,
-            level.original,
-            level.two_player,
-            level.coins,
-            level.star_coins,
-            level.requested_stars,
-            level.wt,
-            level.wt2,
-            level.song_id
-        );
-
-        if level.song_id != 0 {
-            let song_result = sqlx::query_as::<_, Song>("SELECT * FROM songs WHERE id = $1")
-                .bind(level.song_id)
-                .fetch_optional(&db)
-                .await
-                .unwrap_or(None);
-
-            if let Some(song) = song_result {
-                song_string += &format!(
-                    "1~|~{}~|~2~|~{}~|~3~|~{}~|~4~|~{}~|~5~|~{}~|~6~|~~|~10~|~{}~|~",
-                    song.id, song.name, song.author_id, song.author_name, song.size, song.download
-                );
-            }
-        }
+        level_objects.push(LevelObject {
+            description: utilities::crypto::decode_base64(&level.level_desc),
+            set_completes: Some(1),
+            ..LevelObject::from(&level)
+        });
 
-        user_string += &format!("{}:{}:{}|", level.user_id, level.username, level.ext_id);
+        user_strings.push(format!(
+            "{}:{}:{}",
+            level.user_id, level.username, level.ext_id
+        ));
     }
 
-    let result_string_tr = result_string.trim_end_matches('|');
-    let user_string_tr = user_string.trim_end_matches('|');
-    let song_string_tr = song_string.trim_end_matches("~|~");
+    let song_string = songs
+        .values()
+        .map(|song| SongObject::from(song).encode())
+        .collect::<Vec<_>>()
+        .join("~:~");
 
     let mut hash_string = String::new();
 
@@ -1023,43 +864,65 @@ async fn get_levels(
 
     let response_string = format!(
         "{}#{}#{}#{}:{}:10#{}",
-        result_string_tr, user_string_tr, song_string_tr, levels_count.0, page_offset, hash_result
+        wire::encode_list(&level_objects),
+        user_strings.join("|"),
+        song_string,
+        levels_count.0,
+        page_offset,
+        hash_result
     );
 
-    response_string.into_response()
+    if is_cached {
+        cache.insert(search, page_offset, response_string.clone());
+    }
+
+    Ok(response_string.into_response())
 }
 
 async fn download_level(
     Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(storage): Extension<Storage>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
     Form(data): Form<DownloadLevelRequest>,
-) -> Response {
-    if data.secret.unwrap_or_default() != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account_id = data.account_id.unwrap();
+) -> Result<Response> {
+    let Some(account_id) = data.account_id else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
 
-    let account = match utilities::database::get_account_by_id(&db, account_id).await {
+    let account = match utilities::database::get_account_by_id(&db, account_id).await? {
         Some(account) => account,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    if data.hash != account.gjp2 {
-        return CommonResponse::InvalidRequest.into_response();
+    if !credentials::check_session(
+        &db,
+        &config.secret_key,
+        &account,
+        data.hash.as_deref().unwrap_or_default(),
+    )
+    .await?
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
-    let level = match utilities::database::get_level_by_id(&db, data.level_id.unwrap()).await {
+    let Some(level_id) = data.level_id else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
+
+    let level = match utilities::database::get_level_by_id(&db, level_id).await? {
         Some(level) => level,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    let level_string = match read(format!("./data/levels/{}", level.level_id)).await {
-        Ok(content) => String::from_utf8(content).unwrap(),
-        _ => level.level_string.unwrap(),
+    // Older levels only have their data in the row
+    let level_string = match storage.get(&Blob::Level(level.level_id)).await? {
+        Some(content) => String::from_utf8_lossy(&content).into_owned(),
+        None => level.level_string.clone().unwrap_or_default(),
     };
 
     sqlx::query!(
@@ -1070,99 +933,76 @@ async fn download_level(
     .await
     .ok();
 
-    format!("1:{}:2:{}:3:{}:4:{}:5:{}:6:{}:8:{}:9:{}:10:{}:12:{}:13:{}:14:{}:17:{}:43:{}:25:{}:18:{}:19:{}:42:{}:45:{}:15:{}:30:{}:31:{}:28:{}:29:{}:35:{}:36:{}:37:{}:38:{}:39:{}:46:{}:47:{}:40:{}:27:{}#{}#{}",
-        level.level_id,
-        level.level_name,
-        level.level_desc,
-        level_string,
-        level.level_version,
-        level.user_id,
-        "10",
-        level.star_difficulty,
-        level.downloads,
-        level.song_id,
-        level.game_version,
-        level.likes,
-        level.star_demon,
-        level.star_demon_diff,
-        level.star_auto as u8,
-        level.star_stars,
-        level.star_featured,
-        level.star_epic as u8,
-        level.objects,
-        level.level_length,
-        level.original,
-        level.two_player as u8,
-        utilities::make_time(level.upload_date),
-        utilities::make_time(level.update_date),
-        "",
-        level.extra_string,
-        level.coins,
-        level.star_coins as u8,
-        level.requested_stars,
-        level.wt,
-        level.wt2,
-        level.is_ldm as u8,
-        "",
+    // 0 = no copy, 1 = free copy, anything else is a password prefixed with 1
+    let password = if level.password != 0 && utilities::database::has_free_copy(&db, ip).await? {
+        1
+    } else {
+        level.password
+    };
+    let encoded_password = if password == 0 {
+        "0".to_string()
+    } else {
+        utilities::crypto::encode_base64_url(&utilities::crypto::cyclic_xor(
+            &password.to_string(),
+            "26364",
+        ))
+    };
+
+    let hash = utilities::crypto::sha1_salt(
+        &format!(
+            "{},{},{},{},{},{},{},{}",
+            level.user_id,
+            level.star_stars,
+            level.star_demon as u8,
+            level.level_id,
+            level.star_coins as u8,
+            level.star_featured,
+            password,
+            0
+        ),
+        "xI25fpAapCQg",
+    );
+    let level_object = LevelObject {
+        level_string: Some(level_string.clone()),
+        password: Some(encoded_password),
+        upload_date: Some(utilities::make_time(level.upload_date)),
+        update_date: Some(utilities::make_time(level.update_date)),
+        extra_string: Some(level.extra_string.clone()),
+        low_detail_mode: Some(level.is_ldm != 0),
+        ..LevelObject::from(&level)
+    };
+
+    Ok(format!(
+        "{}#{}#{}",
+        level_object.encode(),
         utilities::crypto::hash_level_string(&level_string),
-        utilities::crypto::sha1_salt(
-            &format!(
-                "{},{},{},{},{},{},{},{}",
-                level.user_id,
-                level.star_stars,
-                level.star_demon as u8,
-                level.level_id,
-                level.star_coins as u8,
-                level.star_featured,
-                level.password,
-                0
-            ),
-            "xI25fpAapCQg"
-        )
-    ).into_response()
+        hash
+    )
+    .into_response())
 }
 
 async fn delete_level(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<DeleteLevelRequest>,
-) -> Response {
-    if data.secret != LEVEL_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let user = match utilities::database::get_user_by_id(&db, data.account_id).await {
-        Some(user) => user,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    let level = match utilities::database::get_level_by_id(&db, data.level_id).await {
+    Extension(storage): Extension<Storage>,
+    Extension(cache): Extension<LevelSearchCache>,
+    Authenticated {
+        user, form: data, ..
+    }: Authenticated<DeleteLevelRequest>,
+) -> Result<Response> {
+    let level = match utilities::database::get_level_by_id(&db, data.level_id).await? {
         Some(level) => level,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    if level.user_id != data.user_id {
-        return CommonResponse::InvalidRequest.into_response();
+    if level.user_id != user.user_id {
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     sqlx::query!("DELETE FROM comments WHERE level_id = $1", data.level_id)
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
 
     sqlx::query!(
         "DELETE FROM levels WHERE level_id = $1 AND user_id = $2",
@@ -1170,8 +1010,7 @@ async fn delete_level(
         user.user_id
     )
     .execute(&db)
-    .await
-    .unwrap();
+    .await?;
 
     if level.star_stars > 0 {
         sqlx::query!(
@@ -1180,50 +1019,42 @@ async fn delete_level(
             user.user_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
     }
 
-    let level_path = format!("data/levels/{}", data.level_id);
-    let deleted_level_path = format!("data/levels/deleted/{}", data.level_id);
+    storage
+        .rename(
+            &Blob::Level(data.level_id),
+            &Blob::DeletedLevel(data.level_id),
+        )
+        .await?;
 
-    if try_exists(&level_path).await.is_ok() {
-        rename(level_path, deleted_level_path).await.unwrap();
-    }
+    cache.invalidate(&db).await;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn suggest_stars(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<SuggestStarsRequest>,
-) -> Response {
-    if data.secret != MOD_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if data.hash != account.gjp2.unwrap_or_default() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let role = match utilities::database::get_user_role(&db, data.account_id).await {
+    Extension(cache): Extension<LevelSearchCache>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<SuggestStarsRequest>,
+) -> Result<Response> {
+    let role = match utilities::database::get_privileged_role(&db, account.account_id, ip).await? {
         Some(role) => role,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    let level = match utilities::database::get_level_by_id(&db, data.level_id).await {
+    let level = match utilities::database::get_level_by_id(&db, data.level_id).await? {
         Some(level) => level,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
@@ -1241,13 +1072,13 @@ async fn suggest_stars(
         sqlx::query!(
             "UPDATE levels SET star_featured = $1, star_epic = $2, star_coins = 1, rate_date = $3 WHERE level_id = $4",
             feature, epic, chrono::Utc::now().timestamp() as i32, data.level_id
-        ).execute(&db).await.unwrap();
+        ).execute(&db).await?;
 
         // Rate Level.
         sqlx::query!(
             "UPDATE levels SET star_demon = $1, star_auto = $2, star_difficulty = $3, star_stars = $4, rate_date = $5 WHERE level_id = $6",
             difficulty.is_demon as i32, difficulty.is_auto as i16, difficulty.difficulty, data.stars, chrono::Utc::now().timestamp() as i32, data.level_id
-        ).execute(&db).await.unwrap();
+        ).execute(&db).await?;
 
         // Creator Points.
         sqlx::query!(
@@ -1256,42 +1087,41 @@ async fn suggest_stars(
             level.user_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
 
-        return CommonResponse::Success.into_response();
+        cache.invalidate(&db).await;
+
+        return Ok(CommonResponse::Success.into_response());
     } else if role.action_suggest_rating == 1 {
         sqlx::query!(
             r#"INSERT INTO suggest (suggest_by, suggest_level_id, suggest_difficulty, suggest_stars, suggest_featured, suggest_auto, suggest_demon, timestamp)
                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
-            data.account_id, data.level_id, difficulty.difficulty, data.stars, data.feature, difficulty.is_auto as i32, difficulty.is_demon as i32, chrono::Utc::now().timestamp() as i32
-        ).execute(&db).await.unwrap();
+            account.account_id, data.level_id, difficulty.difficulty, data.stars, data.feature, difficulty.is_auto as i32, difficulty.is_demon as i32, chrono::Utc::now().timestamp() as i32
+        ).execute(&db).await?;
+
+        // Shows up in the Sent tab
+        cache.invalidate(&db).await;
 
-        return CommonResponse::Success.into_response();
+        return Ok(CommonResponse::Success.into_response());
     }
 
-    "-2".into_response()
+    Ok("-2".into_response())
 }
 
 async fn rate_stars(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<RateStarsRequest>,
-) -> Response {
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if data.hash != account.gjp2.unwrap_or_default() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let role = match utilities::database::get_user_role(&db, data.account_id).await {
+    Extension(cache): Extension<LevelSearchCache>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<RateStarsRequest>,
+) -> Result<Response> {
+    let role = match utilities::database::get_privileged_role(&db, account.account_id, ip).await? {
         Some(role) => role,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
@@ -1302,36 +1132,33 @@ async fn rate_stars(
         sqlx::query!(
             "UPDATE levels SET star_demon = $1, star_auto = $2, star_difficulty = $3, rate_date = $4 WHERE level_id = $5",
             difficulty.is_demon as i32, difficulty.is_auto as i16, difficulty.difficulty, chrono::Utc::now().timestamp() as i32, data.level_id
-        ).execute(&db).await.unwrap();
+        ).execute(&db).await?;
+
+        cache.invalidate(&db).await;
     }
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn rate_demon(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<RateDemonRequest>,
-) -> Response {
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if data.hash != account.gjp2.unwrap_or_default() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let role = match utilities::database::get_user_role(&db, data.account_id).await {
+    Extension(cache): Extension<LevelSearchCache>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<RateDemonRequest>,
+) -> Result<Response> {
+    let role = match utilities::database::get_privileged_role(&db, account.account_id, ip).await? {
         Some(role) => role,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
     if role.action_rate_demon != 1 {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     let demon = utilities::gd::get_demon_from_index(data.rating);
@@ -1341,82 +1168,72 @@ async fn rate_demon(
         data.level_id
     )
     .execute(&db)
-    .await
-    .unwrap();
+    .await?;
+
+    cache.invalidate(&db).await;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn report_level(
     Extension(db): Extension<PgPool>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
     Form(data): Form<ReportLevelRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+) -> Result<Response> {
     let report = sqlx::query!(
         "SELECT count(*) FROM reports WHERE level_id = $1 AND hostname = $2",
         data.level_id,
-        addr.to_string()
+        ip.to_string()
     )
     .fetch_one(&db)
-    .await
-    .unwrap();
+    .await?;
 
     if report.count.unwrap_or_default() == 0 {
         let inserted_report = sqlx::query!(
             "INSERT INTO reports (level_id, hostname) VALUES ($1, $2) RETURNING id",
             data.level_id,
-            addr.to_string()
+            ip.to_string()
         )
         .fetch_one(&db)
-        .await
-        .unwrap();
+        .await?;
 
-        inserted_report.id.to_string().into_response()
+        Ok(inserted_report.id.to_string().into_response())
     } else {
-        CommonResponse::InvalidRequest.into_response()
+        Ok(CommonResponse::InvalidRequest.into_response())
     }
 }
 
 async fn update_description(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<UpdateDescriptionRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if data.hash != account.gjp2.unwrap_or_default() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+    Extension(cache): Extension<LevelSearchCache>,
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<UpdateDescriptionRequest>,
+) -> Result<Response> {
     sqlx::query!(
         "UPDATE levels SET level_desc = $1 WHERE level_id = $2 AND ext_id = $3",
         data.level_description,
         data.level_id,
-        &data.account_id.to_string()
+        &account.account_id.to_string()
     )
     .execute(&db)
-    .await
-    .unwrap();
+    .await?;
+
+    cache.invalidate(&db).await;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn get_level_scores(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<GetLevelScoresRequest>,
-) -> Response {
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<GetLevelScoresRequest>,
+) -> Result<Response> {
     let attempts = (data.attempts.unwrap_or_default() - 8354).min(0);
     let clicks = (data.clicks.unwrap_or_default() - 3991).min(0);
     let time = (data.time.unwrap_or_default() - 4085).min(0);
@@ -1433,29 +1250,41 @@ async fn get_level_scores(
         "SELECT * FROM level_scores WHERE account_id = $1 AND level_id = $2 AND daily_id {} 0",
         if daily_id > 0 { ">" } else { "=" }
     ))
-    .bind(data.account_id)
+    .bind(account.account_id)
     .bind(data.level_id)
     .fetch_one(&db)
     .await;
 
-    if old_score.is_err() {
-        sqlx::query!(
-            r#"
-            INSERT INTO level_scores (account_id, level_id, percent, upload_date, coins, attempts, clicks, time, progresses, daily_id)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
-            "#,
-            data.account_id, data.level_id, data.percent, chrono::Utc::now().timestamp() as i32, coins, attempts, clicks, time, progresses, daily_id
-        ).execute(&db).await.unwrap();
-    } else if old_score.unwrap().percent < data.percent {
-        sqlx::query!(
-            "UPDATE level_scores SET percent = $1, upload_date = $2, coins = $3, attempts = $4, clicks = $5, time = $6, progresses = $7, daily_id = $8 WHERE account_id = $9 AND level_id = $10",
-            data.percent, chrono::Utc::now().timestamp() as i32, coins, attempts, clicks, time, progresses, daily_id, data.account_id, data.level_id
-        ).execute(&db).await.unwrap();
+    match old_score {
+        Err(_) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO level_scores (account_id, level_id, percent, upload_date, coins, attempts, clicks, time, progresses, daily_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                "#,
+                account.account_id, data.level_id, data.percent, chrono::Utc::now().timestamp() as i32, coins, attempts, clicks, time, progresses, daily_id
+            ).execute(&db).await?;
+        }
+        Ok(old_score) if old_score.percent < data.percent => {
+            sqlx::query!(
+                "UPDATE level_scores SET percent = $1, upload_date = $2, coins = $3, attempts = $4, clicks = $5, time = $6, progresses = $7, daily_id = $8 WHERE account_id = $9 AND level_id = $10",
+                data.percent, chrono::Utc::now().timestamp() as i32, coins, attempts, clicks, time, progresses, daily_id, account.account_id, data.level_id
+            ).execute(&db).await?;
+        }
+        Ok(_) => {}
     }
 
     if data.percent < 0 || data.percent > 100 {
-        utilities::database::ban_user(&db, data.account_id).await;
-        return CommonResponse::InvalidRequest.into_response();
+        utilities::database::ban_account(
+            &db,
+            account.account_id,
+            BanScope::Leaderboard,
+            "Invalid level percentage",
+            None,
+            0,
+        )
+        .await?;
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     #[allow(unused_assignments)]
@@ -1463,75 +1292,91 @@ async fn get_level_scores(
 
     match data.leaderboard_type {
         0 => {
-            let friends = utilities::database::get_friends(&db, data.account_id).await;
+            let friends = utilities::database::get_friends(&db, account.account_id).await?;
 
             scores = sqlx::query_as!(
                 LevelScore,
-                "SELECT * FROM level_scores WHERE level_id = $1 AND account_id = ANY($2) ORDER BY percent DESC",
-                data.level_id, &friends
-            ).fetch_all(&db).await.unwrap();
+                r#"
+                SELECT * FROM level_scores
+                    WHERE level_id = $1 AND account_id = ANY($2)
+                      AND NOT EXISTS (SELECT 1 FROM active_bans WHERE active_bans.account_id = level_scores.account_id AND scope = $3)
+                ORDER BY percent DESC
+            "#,
+                data.level_id, &friends, BanScope::Leaderboard as BanScope
+            ).fetch_all(&db).await?;
         }
         1 => {
             scores = sqlx::query_as!(
                 LevelScore,
-                "SELECT * FROM level_scores WHERE level_id = $1 ORDER BY percent DESC",
-                data.level_id
+                r#"
+                SELECT * FROM level_scores
+                    WHERE level_id = $1
+                      AND NOT EXISTS (SELECT 1 FROM active_bans WHERE active_bans.account_id = level_scores.account_id AND scope = $2)
+                ORDER BY percent DESC
+            "#,
+                data.level_id,
+                BanScope::Leaderboard as BanScope
             )
             .fetch_all(&db)
-            .await
-            .unwrap();
+            .await?;
         }
         2 => {
             scores = sqlx::query_as!(
                 LevelScore,
-                "SELECT * FROM level_scores WHERE level_id = $1 AND upload_date > $2 ORDER BY percent DESC",
-                data.level_id, (chrono::Utc::now().timestamp() - 604800) as i32
-            ).fetch_all(&db).await.unwrap();
+                r#"
+                SELECT * FROM level_scores
+                    WHERE level_id = $1 AND upload_date > $2
+                      AND NOT EXISTS (SELECT 1 FROM active_bans WHERE active_bans.account_id = level_scores.account_id AND scope = $3)
+                ORDER BY percent DESC
+            "#,
+                data.level_id, (chrono::Utc::now().timestamp() - 604800) as i32, BanScope::Leaderboard as BanScope
+            ).fetch_all(&db).await?;
         }
         _ => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     }
 
-    let mut response = String::new();
+    let account_ids = scores
+        .iter()
+        .map(|score| score.account_id)
+        .collect::<Vec<_>>();
+    let users = utilities::database::get_users_by_ids(&db, &account_ids).await?;
+
+    let mut response = Vec::new();
 
     for (index, score) in scores.iter().enumerate() {
-        let user = utilities::database::get_user_by_id(&db, score.account_id)
-            .await
-            .unwrap();
-        if user.is_banned == 1 {
+        let Some(user) = users.get(&score.account_id) else {
             continue;
-        }
+        };
 
-        response.push_str(&format!(
-            "1:{}:2:{}:3:{}:6:{}:9:{}:10:{}:11:{}:13:{}:14:{}:15:{}:16:{}:42:{}|",
-            user.username,
-            user.user_id,
-            score.percent,
-            index + 1,
-            user.icon,
-            user.color1,
-            user.color2,
-            user.user_coins,
-            user.icon_type,
-            user.special,
-            score.account_id,
-            utilities::make_time(score.time as i64)
-        ));
+        response.push(LevelScoreUser {
+            username: user.username.clone(),
+            user_id: user.user_id,
+            percent: score.percent,
+            rank: index as i32 + 1,
+            icon: user.icon,
+            color1: user.color1,
+            color2: user.color2,
+            coins: user.user_coins,
+            icon_type: user.icon_type,
+            special: user.special,
+            account_id: score.account_id,
+            date: utilities::make_time(score.time as i64),
+        });
     }
 
-    let _ = response.trim_end_matches("|");
-    response.into_response()
+    Ok(wire::encode_list(&response).into_response())
 }
 
 async fn upload_level_comment(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<UploadLevelCommentRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+    Authenticated {
+        account,
+        user,
+        form: data,
+    }: Authenticated<UploadLevelCommentRequest>,
+) -> Result<Response> {
     let percent = data.percent.unwrap_or_default();
 
     // TODO: #clone() is skill issue
@@ -1543,40 +1388,28 @@ async fn upload_level_comment(
     ];
     if data.chk != utilities::crypto::generate_checksum(checksum, "29481", "0xPT6iUrtws0J") {
         tracing::info!("failed to verify checksum");
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if data.hash != account.gjp2.unwrap_or_default() {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     if data.comment.len() > 140 {
-        return "temp_0_You cannot post comments above 140 characters!".into_response();
+        return Ok("temp_0_You cannot post comments above 140 characters!".into_response());
     }
     if !(0..=100).contains(&percent) {
-        return "temp_0_Invalid percentage!".into_response();
+        return Ok("temp_0_Invalid percentage!".into_response());
     }
 
-    let user = match utilities::database::get_user_by_id(&db, data.account_id).await {
-        Some(user) => user,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
+    if let Some(ban) =
+        utilities::database::get_active_ban(&db, account.account_id, BanScope::Comment).await?
+    {
+        return Ok(CommentsResponse::from(ban).into_response());
+    }
 
     sqlx::query!(
         r#"
         INSERT INTO comments (username, comment, level_id, user_id, timestamp, percent)
         VALUES ($1, $2, $3, $4, $5, $6)
         "#,
-        data.username,
+        account.username,
         data.comment,
         data.level_id,
         user.user_id,
@@ -1584,147 +1417,187 @@ async fn upload_level_comment(
         percent
     )
     .execute(&db)
-    .await
-    .unwrap();
+    .await?;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn get_level_comments(
     Extension(db): Extension<PgPool>,
     Form(data): Form<GetLevelCommentsRequest>,
-) -> Response {
-    let level = match utilities::database::get_level_by_id(&db, data.level_id).await {
+) -> Result<Response> {
+    let level = match utilities::database::get_level_by_id(&db, data.level_id).await? {
         Some(level) => level,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    let mut comments_response = String::new();
+    let mut comments_response = Vec::new();
     let comments = sqlx::query!(
         "SELECT * FROM comments WHERE level_id = $1 LIMIT $2",
         data.level_id,
         data.count.unwrap_or(10) as i64
     )
     .fetch_all(&db)
-    .await
-    .unwrap();
+    .await?;
+
+    let user_ids = comments
+        .iter()
+        .map(|comment| comment.user_id)
+        .collect::<Vec<_>>();
+    let users = utilities::database::get_users_by_ids(&db, &user_ids).await?;
+    // Roles are assigned to accounts, `ext_id` is the account ID of a registered user
+    let account_ids = users
+        .values()
+        .filter_map(|user| user.ext_id.parse().ok())
+        .collect::<Vec<_>>();
+    let roles = utilities::database::get_user_roles_by_ids(&db, &account_ids).await?;
 
     for comment in comments {
-        let role = utilities::database::get_user_role(&db, comment.user_id)
-            .await
-            .unwrap();
-        let user = utilities::database::get_user_by_id(&db, comment.user_id)
-            .await
-            .unwrap();
-
-        // Comment Structure
-        comments_response.push_str(&format!(
-            "1~{}~2~{}~3~{}~4~{}~5~{}~6~{}~7~{}~8~{}~9~{}~10~{}~11~{}~12~{}:",
-            level.level_id,
-            comment.comment,
-            comment.user_id,
-            comment.likes,
-            0, // The amount of dislikes the comment has (unused)
-            comment.comment_id,
-            comment.is_spam as u8,
-            comment.user_id,
-            utilities::make_time(comment.timestamp as i64),
-            comment.percent,
-            role.mod_badge_level,
-            role.comment_color
-        ));
+        let Some(user) = users.get(&comment.user_id) else {
+            continue;
+        };
+        let role = user
+            .ext_id
+            .parse()
+            .ok()
+            .and_then(|account_id: i32| roles.get(&account_id).cloned())
+            .unwrap_or_default();
+
+        let comment_object = LevelComment {
+            level_id: level.level_id,
+            comment: comment.comment,
+            user_id: comment.user_id,
+            likes: comment.likes,
+            dislikes: 0, // unused by the game
+            comment_id: comment.comment_id,
+            is_spam: comment.is_spam,
+            account_id: comment.user_id,
+            age: utilities::make_time(comment.timestamp as i64),
+            percent: comment.percent,
+            mod_badge: role.mod_badge_level,
+            color: role.comment_color,
+        };
+        let author = CommentAuthor {
+            username: comment.username,
+            icon: user.icon,
+            color1: user.color1,
+            color2: user.color2,
+            icon_type: user.icon_type,
+            glow: user.acc_glow,
+            account_id: user.ext_id.clone(),
+        };
 
-        // Author/User Structure
-        comments_response.push_str(&format!(
-            "1~{}~9~{}~10~{}~11~{}~14~{}~15~{}~16~{}|",
-            comment.username,
-            user.icon,
-            user.color1,
-            user.color2,
-            user.icon_type,
-            user.acc_glow,
-            user.ext_id
-        ));
+        comments_response.push(format!("{}:{}", comment_object.encode(), author.encode()));
     }
 
-    let _ = comments_response.trim_end_matches("|");
-    format!(
+    Ok(format!(
         "{}\n#{}:{}:{}",
-        comments_response,
+        comments_response.join("|"),
         data.page * data.count.unwrap_or(10),
         data.page,
         1
     )
-    .into_response()
+    .into_response())
 }
 
 async fn delete_level_comment(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<DeleteLevelCommentRequest>
-) -> Response {
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if data.hash != account.gjp2.unwrap_or_default() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let user = match utilities::database::get_user_by_id(&db, data.account_id).await {
-        Some(user) => user,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    let level = match utilities::database::get_level_by_id(&db, data.level_id).await {
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        user,
+        form: data,
+    }: Authenticated<DeleteLevelCommentRequest>,
+) -> Result<Response> {
+    let level = match utilities::database::get_level_by_id(&db, data.level_id).await? {
         Some(level) => level,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    let result = sqlx::query!("DELETE FROM comments WHERE user_id = $1 AND comment_id = $2", user.user_id, data.comment_id)
-        .execute(&db).await.unwrap();
+    let result = sqlx::query!(
+        "DELETE FROM comments WHERE user_id = $1 AND comment_id = $2",
+        user.user_id,
+        data.comment_id
+    )
+    .execute(&db)
+    .await?;
 
     if result.rows_affected() == 0 {
-        let role = match utilities::database::get_user_role(&db, data.account_id).await {
-            Some(role) => role,
-            None => {
-                return CommonResponse::InvalidRequest.into_response();
-            }
-        };
+        let role =
+            match utilities::database::get_privileged_role(&db, account.account_id, ip).await? {
+                Some(role) => role,
+                None => {
+                    return Ok(CommonResponse::InvalidRequest.into_response());
+                }
+            };
 
         if level.user_id == user.user_id || role.action_delete_comment == 1 {
-            sqlx::query!("DELETE FROM comments WHERE comment_id = $1 AND level_id = $2", data.comment_id, data.level_id)
-                .execute(&db).await.unwrap();
+            sqlx::query!(
+                "DELETE FROM comments WHERE comment_id = $1 AND level_id = $2",
+                data.comment_id,
+                data.level_id
+            )
+            .execute(&db)
+            .await?;
         }
     }
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 pub fn init() -> Router {
     Router::new()
-        .route("/database/uploadGJLevel21.php", post(upload_level))
-        .route("/database/getGJLevels21.php", post(get_levels))
-        .route("/database/downloadGJLevel22.php", post(download_level))
-        .route("/database/deleteGJLevelUser20.php", post(delete_level))
-        .route("/database/suggestGJStars20.php", post(suggest_stars))
-        .route("/database/rateGJStars211.php", post(rate_stars))
-        .route("/database/rateGJDemon21.php", post(rate_demon))
-        .route("/database/reportGJLevel.php", post(report_level))
-        .route("/database/updateGJDesc20.php", post(update_description))
-        .route("/database/getGJLevelScores211.php", post(get_level_scores))
+        .route(
+            "/database/uploadGJLevel21.php",
+            post(Secret::Common, upload_level),
+        )
+        .route(
+            "/database/getGJLevels21.php",
+            post(Secret::Common, get_levels),
+        )
+        .route(
+            "/database/downloadGJLevel22.php",
+            post(Secret::Common, download_level),
+        )
+        .route(
+            "/database/deleteGJLevelUser20.php",
+            post(Secret::Level, delete_level),
+        )
+        .route(
+            "/database/suggestGJStars20.php",
+            post(Secret::Mod, suggest_stars),
+        )
+        .route(
+            "/database/rateGJStars211.php",
+            post(Secret::Common, rate_stars),
+        )
+        .route("/database/rateGJDemon21.php", post(Secret::Mod, rate_demon))
+        .route(
+            "/database/reportGJLevel.php",
+            post(Secret::Common, report_level),
+        )
+        .route(
+            "/database/updateGJDesc20.php",
+            post(Secret::Common, update_description),
+        )
+        .route(
+            "/database/getGJLevelScores211.php",
+            post(Secret::Common, get_level_scores),
+        )
         .route(
             "/database/uploadGJComment21.php",
-            post(upload_level_comment),
+            post(Secret::Common, upload_level_comment),
+        )
+        .route(
+            "/database/getGJComments21.php",
+            post(Secret::Common, get_level_comments),
+        )
+        .route(
+            "/database/deleteGJComment20.php",
+            post(Secret::Common, delete_level_comment),
         )
-        .route("/database/getGJComments21.php", post(get_level_comments))
-        .route("/database/deleteGJComment20.php", post(delete_level_comment))
 }
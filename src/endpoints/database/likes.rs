@@ -0,0 +1,98 @@
+use std::net::IpAddr;
+
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::Form;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    middleware::client::{Secret, post},
+    middleware::ip_ban::ClientIp,
+    types::{error::Result, response::CommonResponse},
+};
+
+#[derive(Deserialize, Debug)]
+#[allow(unused)]
+struct LikeItemRequest {
+    #[serde(rename = "gameVersion")]
+    game_version: i32,
+    #[serde(rename = "binaryVersion")]
+    binary_version: i32,
+    #[serde(rename = "udid")]
+    id: String,
+    #[serde(rename = "uuid")]
+    user_id: i32,
+    #[serde(rename = "itemID")]
+    item_id: i32,
+    like: i32,
+    #[serde(rename = "type")]
+    item_type: i32,
+    secret: String,
+}
+
+// Players don't have to be logged in to like something, so every address gets one like or
+// dislike per item. Each one is kept in `actions_likes`, trending levels are ranked on them.
+async fn like_item(
+    Extension(db): Extension<PgPool>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(data): Form<LikeItemRequest>,
+) -> Result<Response> {
+    // 1 = level, 2 = level comment, 3 = account comment, 4 = list
+    let (table, key) = match data.item_type {
+        1 => ("levels", "level_id"),
+        2 => ("comments", "comment_id"),
+        3 => ("acc_comments", "comment_id"),
+        4 => ("lists", "list_id"),
+        _ => return Ok(CommonResponse::InvalidRequest.into_response()),
+    };
+    let ip = match ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+
+    let already_liked = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM actions_likes WHERE item_id = $1 AND type = $2 AND ip = $3)",
+        data.item_id,
+        data.item_type,
+        ip
+    )
+    .fetch_one(&db)
+    .await?
+    .unwrap_or_default();
+    if already_liked {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    let updated = sqlx::query(&format!(
+        "UPDATE {table} SET likes = likes + $1 WHERE {key} = $2"
+    ))
+    .bind(if data.like == 1 { 1 } else { -1 })
+    .bind(data.item_id)
+    .execute(&db)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    }
+
+    sqlx::query!(
+        "INSERT INTO actions_likes (item_id, type, is_like, ip) VALUES ($1, $2, $3, $4)",
+        data.item_id,
+        data.item_type,
+        (data.like == 1) as i16,
+        ip
+    )
+    .execute(&db)
+    .await?;
+
+    Ok(CommonResponse::Success.into_response())
+}
+
+pub fn init() -> Router {
+    Router::new().route(
+        "/database/likeGJItem211.php",
+        post(Secret::Common, like_item),
+    )
+}
@@ -1,5 +1,6 @@
 pub mod accounts;
 pub mod levels;
+pub mod likes;
 pub mod mods;
 pub mod rewards;
 pub mod scores;
@@ -1,9 +1,17 @@
-use axum::{Extension, Router, response::IntoResponse, routing::post};
-use axum_extra::extract::Form;
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+};
 use serde::Deserialize;
 use sqlx::PgPool;
 
-use crate::{types::response::CommonResponse, utilities};
+use crate::{
+    extractors::auth::Authenticated,
+    middleware::client::{Secret, post},
+    middleware::ip_ban::ClientIp,
+    types::{error::Result, response::CommonResponse},
+    utilities,
+};
 
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
@@ -16,55 +24,31 @@ struct RequestUserAccessRequest {
     id: String,
     #[serde(rename = "uuid")]
     user_id: i32,
-    #[serde(rename = "accountID")]
-    account_id: i32,
-    #[serde(rename = "gjp2")]
-    hash: String,
     secret: String,
 }
 
 async fn request_user_access(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<RequestUserAccessRequest>,
-) -> impl IntoResponse {
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated { account, .. }: Authenticated<RequestUserAccessRequest>,
+) -> Result<Response> {
+    let role = match utilities::database::get_privileged_role(&db, account.account_id, ip).await? {
+        Some(role) => role,
         None => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let role_assign = sqlx::query!(
-        "SELECT role_id FROM role_assign WHERE account_id = $1",
-        data.account_id as i64
-    )
-    .fetch_optional(&db)
-    .await
-    .unwrap();
-
-    if role_assign.is_none() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let role = sqlx::query!(
-        "SELECT action_request_mod, mod_badge_level FROM roles WHERE role_id = $1",
-        role_assign.unwrap().role_id
-    )
-    .fetch_one(&db)
-    .await
-    .unwrap();
-
     if role.action_request_mod == 0 {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
-    format!("{}", role.mod_badge_level.max(2)).into_response()
+    Ok(format!("{}", role.mod_badge_level.max(2)).into_response())
 }
 
 pub fn init() -> Router {
-    Router::new().route("/database/requestUserAccess.php", post(request_user_access))
+    Router::new().route(
+        "/database/requestUserAccess.php",
+        post(Secret::Common, request_user_access),
+    )
 }
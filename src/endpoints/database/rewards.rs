@@ -1,12 +1,17 @@
-use axum::{Extension, Router, response::IntoResponse, routing::post};
-use axum_extra::extract::Form;
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+};
 use rand::prelude::*;
 use serde::Deserialize;
 use sqlx::PgPool;
 
-use crate::{types::response::CommonResponse, utilities};
-
-use super::COMMON_SECRET;
+use crate::{
+    extractors::auth::Authenticated,
+    middleware::client::{Secret, post},
+    types::error::Result,
+    utilities,
+};
 
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
@@ -19,10 +24,6 @@ struct RewardsRequest {
     id: String,
     #[serde(rename = "uuid")]
     user_id: i32,
-    #[serde(rename = "accountID")]
-    account_id: i32,
-    #[serde(rename = "gjp2")]
-    hash: String,
     #[serde(rename = "rewardType")]
     reward_type: Option<i32>, // TODO: enum
     secret: String,
@@ -34,33 +35,14 @@ struct RewardsRequest {
 
 async fn get_rewards(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<RewardsRequest>,
-) -> impl IntoResponse {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+    Authenticated {
+        account,
+        user,
+        form: data,
+    }: Authenticated<RewardsRequest>,
+) -> Result<Response> {
     let checksum = data.checksum.unwrap_or_default();
     let reward_type = data.reward_type.unwrap_or_default();
-    let hash = data.hash;
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if account.gjp2.unwrap_or_default() != hash {
-        return "-1".into_response();
-    }
-
-    let user = match utilities::database::get_user_by_id(&db, data.account_id).await {
-        Some(user) => user,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
 
     let mut rng = StdRng::from_os_rng();
     let current_time = chrono::Utc::now().timestamp();
@@ -99,7 +81,7 @@ async fn get_rewards(
 
     if reward_type == 1 {
         if chest1_left != 0 {
-            return "-1".into_response();
+            return Ok("-1".into_response());
         }
 
         chest1_count += 1;
@@ -112,11 +94,10 @@ async fn get_rewards(
             user.user_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
     } else if reward_type == 2 {
         if chest2_left != 0 {
-            return "-1".into_response();
+            return Ok("-1".into_response());
         }
 
         chest2_count += 1;
@@ -129,11 +110,11 @@ async fn get_rewards(
             user.user_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
     }
 
-    let decoded_checksum = utilities::crypto::decode_base64_url_raw(&checksum[5..]);
+    let decoded_checksum =
+        utilities::crypto::decode_base64_url_raw(checksum.get(5..).unwrap_or_default());
     let decoded_checksum =
         utilities::crypto::cyclic_xor(&String::from_utf8_lossy(&decoded_checksum), "59182");
 
@@ -143,7 +124,7 @@ async fn get_rewards(
         data.user_id,
         decoded_checksum,
         data.id,
-        data.account_id,
+        account.account_id,
         chest1_left,
         chest1_stuff,
         chest1_count,
@@ -157,9 +138,12 @@ async fn get_rewards(
     let b64_reward = utilities::crypto::encode_base64_url(&xor_reward);
     let hash_reward = utilities::crypto::sha1_salt(&response, "pC26fpYaQCtg");
 
-    format!("{}|{}", b64_reward, hash_reward).into_response()
+    Ok(format!("{}|{}", b64_reward, hash_reward).into_response())
 }
 
 pub fn init() -> Router {
-    Router::new().route("/database/getGJRewards.php", post(get_rewards))
+    Router::new().route(
+        "/database/getGJRewards.php",
+        post(Secret::Common, get_rewards),
+    )
 }
@@ -1,9 +1,21 @@
-use axum::{Extension, Router, response::IntoResponse, routing::post};
+use axum::{
+    Extension, Router,
+    response::{IntoResponse, Response},
+};
 use axum_extra::extract::Form;
 use serde::Deserialize;
 use sqlx::PgPool;
 
-use crate::types::{database::User, response::CommonResponse};
+use crate::{
+    middleware::client::{Secret, post},
+    types::{
+        database::{BanScope, User},
+        error::Result,
+        objects::LeaderboardUser,
+        response::CommonResponse,
+    },
+    utilities::wire,
+};
 
 #[derive(Deserialize, Debug, Default)]
 #[allow(unused)]
@@ -29,7 +41,7 @@ struct GetScoresRequest {
 async fn get_scores(
     Extension(db): Extension<PgPool>,
     Form(data): Form<GetScoresRequest>,
-) -> impl IntoResponse {
+) -> Result<Response> {
     let mut user_results = Vec::new();
 
     match data.search_type.as_str() {
@@ -39,16 +51,16 @@ async fn get_scores(
                 r#"
                 SELECT * FROM users
                     WHERE stars >= 1
-                      AND is_banned = 0
-                      AND ip NOT IN (SELECT ip FROM banned_ips)
+                      AND NOT EXISTS (SELECT 1 FROM active_bans WHERE account_id::TEXT = users.ext_id AND scope = $2)
+                      AND NOT EXISTS (SELECT 1 FROM banned_ips WHERE try_inet(users.ip) <<= try_inet(banned_ips.ip))
                 ORDER BY stars + moons DESC
                 LIMIT $1
             "#,
-                data.count.min(0).max(100) as i64
+                data.count.min(0).max(100) as i64,
+                BanScope::Leaderboard as BanScope
             )
             .fetch_all(&db)
-            .await
-            .unwrap();
+            .await?;
 
             user_results = users;
         }
@@ -58,16 +70,17 @@ async fn get_scores(
                 r#"
                 SELECT * FROM users
                     WHERE creator_points > 0
-                      AND is_creator_banned = 0
-                      AND ip NOT IN (SELECT ip FROM banned_ips)
+                      AND NOT EXISTS (SELECT 1 FROM active_bans WHERE account_id::TEXT = users.ext_id AND scope IN ($2, $3))
+                      AND NOT EXISTS (SELECT 1 FROM banned_ips WHERE try_inet(users.ip) <<= try_inet(banned_ips.ip))
                 ORDER BY creator_points DESC
                 LIMIT $1
             "#,
-                data.count.min(0).max(100) as i64
+                data.count.min(0).max(100) as i64,
+                BanScope::Leaderboard as BanScope,
+                BanScope::Creator as BanScope
             )
             .fetch_all(&db)
-            .await
-            .unwrap();
+            .await?;
 
             user_results = creators;
         }
@@ -84,12 +97,13 @@ async fn get_scores(
                 SELECT * FROM users
                     WHERE (stars BETWEEN (SELECT stars FROM current_user) - 1000 AND (SELECT stars FROM current_user) + 1000)
                       AND (moons BETWEEN (SELECT moons FROM current_user) - 500 AND (SELECT moons FROM current_user) + 500)
+                      AND NOT EXISTS (SELECT 1 FROM active_bans WHERE account_id::TEXT = users.ext_id AND scope = $3)
+                      AND NOT EXISTS (SELECT 1 FROM banned_ips WHERE try_inet(users.ip) <<= try_inet(banned_ips.ip))
                 ORDER BY (ABS(stars - (SELECT stars FROM current_user)) + ABS(moons - (SELECT moons FROM current_user)))
                 LIMIT $2
-            "#, data.account_id.to_string(), data.count.min(0).max(50) as i64)
+            "#, data.account_id.to_string(), data.count.min(0).max(50) as i64, BanScope::Leaderboard as BanScope)
                 .fetch_all(&db)
-                .await
-                .unwrap();
+                .await?;
 
             user_results = relatives;
         }
@@ -100,8 +114,7 @@ async fn get_scores(
                 data.count.min(0).max(50) as i64
             )
             .fetch_all(&db)
-            .await
-            .unwrap();
+            .await?;
 
             let mut friend_ids = Vec::new();
             for friend in user_friends {
@@ -114,56 +127,60 @@ async fn get_scores(
 
             let friends = sqlx::query_as!(
                 User,
-                "SELECT * FROM users WHERE ext_id IN ($1, $2) ORDER BY stars DESC",
+                r#"
+                SELECT * FROM users
+                    WHERE ext_id IN ($1, $2)
+                      AND NOT EXISTS (SELECT 1 FROM active_bans WHERE account_id::TEXT = users.ext_id AND scope = $3)
+                      AND NOT EXISTS (SELECT 1 FROM banned_ips WHERE try_inet(users.ip) <<= try_inet(banned_ips.ip))
+                ORDER BY stars DESC
+            "#,
                 &data.account_id.to_string(),
-                friend_ids.join(", ")
+                friend_ids.join(", "),
+                BanScope::Leaderboard as BanScope
             )
             .fetch_all(&db)
-            .await
-            .unwrap();
+            .await?;
 
             user_results = friends;
         }
         &_ => (),
     }
 
-    let mut leaderboard_string = String::new();
-    let mut leaderboard_place = 0;
-
-    for user in user_results {
-        leaderboard_place += 1;
-
-        let user_string = format!(
-            "1:{}:2:{}:13:{}:17:{}:6:{}:9:{}:10:{}:11:{}:51:{}:14:{}:15:{}:16:{}:3:{}:8:{}:4:{}:7:{}:46:{}:52:{}|",
-            user.username,
-            user.user_id,
-            user.coins,
-            user.user_coins,
-            leaderboard_place,
-            user.icon,
-            user.color1,
-            user.color2,
-            user.color3,
-            user.icon_type,
-            user.special,
-            data.account_id,
-            user.stars,
-            user.creator_points.round(),
-            user.demons,
-            data.account_id,
-            user.diamonds,
-            user.moons
-        );
-        leaderboard_string.push_str(&user_string);
-    }
-
-    if leaderboard_string.is_empty() {
-        return CommonResponse::InvalidRequest.into_response();
+    let leaderboard = user_results
+        .iter()
+        .enumerate()
+        .map(|(index, user)| LeaderboardUser {
+            username: user.username.clone(),
+            user_id: user.user_id,
+            stars: user.stars,
+            demons: user.demons,
+            rank: index as i32 + 1,
+            account_highlight: data.account_id.to_string(),
+            creator_points: user.creator_points.round() as i32,
+            icon: user.icon,
+            color1: user.color1,
+            color2: user.color2,
+            coins: user.coins,
+            icon_type: user.icon_type,
+            special: user.special,
+            account_id: user.ext_id.clone(),
+            user_coins: user.user_coins,
+            diamonds: user.diamonds,
+            color3: user.color3,
+            moons: user.moons,
+        })
+        .collect::<Vec<_>>();
+
+    if leaderboard.is_empty() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
-    leaderboard_string.into_response()
+    Ok(wire::encode_list(&leaderboard).into_response())
 }
 
 pub fn init() -> Router {
-    Router::new().route("/database/getGJScores20.php", post(get_scores))
+    Router::new().route(
+        "/database/getGJScores20.php",
+        post(Secret::Common, get_scores),
+    )
 }
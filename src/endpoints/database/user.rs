@@ -1,26 +1,35 @@
-use std::fmt::Write;
-use std::net::SocketAddr;
-
 use axum::{
     Extension, Router,
-    extract::ConnectInfo,
     response::{IntoResponse, Response},
-    routing::post,
 };
 use axum_extra::extract::Form;
 use chrono::Utc;
 use serde::{Deserialize, Deserializer};
 use sqlx::PgPool;
+use tracing::warn;
 
 use crate::{
+    extractors::auth::Authenticated,
+    middleware::client::{Secret, post},
+    middleware::ip_ban::ClientIp,
     types::{
-        database::{FriendRequest, Message},
-        response::CommonResponse,
+        database::{BanScope, FriendRequest, Message},
+        error::Result,
+        objects::{
+            AccountComment, FriendRequestObject, MessageObject, UserProfile, UserSearchResult,
+        },
+        response::{CommentsResponse, CommonResponse},
+    },
+    utilities::{
+        self, crypto,
+        database::get_user_by_id,
+        game_save::SaveStats,
+        wire::{self, WireObject},
     },
-    utilities::{self, crypto, database::get_user_by_id},
 };
 
-use super::COMMON_SECRET;
+// In seconds, how recent a backup has to be for `updateGJUserScore` to be checked against it
+const BACKUP_CHECK_WINDOW: i64 = 10 * 60;
 
 // https://github.com/tokio-rs/axum/discussions/2380#discussioncomment-7705720
 // luv luv!
@@ -36,8 +45,6 @@ where
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
 struct UpdateRequest {
-    #[serde(rename = "accountID")]
-    account_id: i32,
     #[serde(rename = "userName")]
     username: String,
     secret: String,
@@ -89,8 +96,6 @@ struct UpdateRequest {
     binary_version: i32,
     #[serde(rename = "udid")]
     id: String,
-    #[serde(rename = "gjp2")]
-    hash: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -142,10 +147,6 @@ struct PostCommentRequest {
     #[serde(rename = "udid")]
     id: Option<String>,
     uuid: Option<i32>,
-    #[serde(default, rename = "accountID", deserialize_with = "take_first")]
-    account_id: Option<i32>,
-    #[serde(rename = "gjp2")]
-    hash: Option<String>,
     #[serde(rename = "userName")]
     username: Option<String>,
     comment: Option<String>,
@@ -166,10 +167,6 @@ struct DeleteCommentRequest {
     #[serde(rename = "udid")]
     id: String,
     uuid: i32,
-    #[serde(default, rename = "accountID", deserialize_with = "take_first")]
-    account_id: Option<i32>,
-    #[serde(rename = "gjp2")]
-    hash: String,
     #[serde(rename = "commentID")]
     comment_id: i32,
     secret: String,
@@ -182,10 +179,6 @@ struct DeleteCommentRequest {
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
 struct UpdateUserSettingsRequest {
-    #[serde(default, rename = "accountID", deserialize_with = "take_first")]
-    account_id: Option<i32>,
-    #[serde(rename = "gjp2")]
-    hash: String,
     // Allow Messages From:
     // ALL(0), FRIENDS(1), NONE(2)
     #[serde(rename = "mS")]
@@ -228,10 +221,6 @@ struct UploadMessageRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "toAccountID")]
     pub to_account_id: i32,
     pub subject: String,
@@ -249,10 +238,6 @@ struct GetFriendsRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     pub page: i32,
     pub total: i32,
     pub secret: String,
@@ -269,10 +254,6 @@ struct GetMessagesRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     pub page: i32,
     pub total: i32,
     pub secret: String,
@@ -290,10 +271,6 @@ struct DownloadMessageRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "messageID")]
     pub message_id: i32,
     pub secret: String,
@@ -311,10 +288,6 @@ struct DeleteMessageRequest {
     #[serde(rename = "udid")]
     pub id: String,
     pub uuid: i32,
-    #[serde(rename = "accountID")]
-    pub account_id: i32,
-    #[serde(rename = "gjp2")]
-    pub hash: String,
     #[serde(rename = "messageID")]
     pub message_id: Option<i32>,
     pub messages: Option<String>,
@@ -323,28 +296,28 @@ struct DeleteMessageRequest {
     pub is_sender: Option<i32>,
 }
 
-async fn get_friend_requests_count(db: &PgPool, account_id: i32) -> Option<i64> {
-    sqlx::query_scalar!(
+async fn get_friend_requests_count(db: &PgPool, account_id: i32) -> Result<i64> {
+    Ok(sqlx::query_scalar!(
         "SELECT count(*) FROM friend_requests WHERE to_account_id = $1",
         account_id
     )
     .fetch_one(db)
-    .await
-    .unwrap_or_default()
+    .await?
+    .unwrap_or_default())
 }
 
-async fn get_messages_count(db: &PgPool, account_id: i32) -> Option<i64> {
-    sqlx::query_scalar!(
+async fn get_messages_count(db: &PgPool, account_id: i32) -> Result<i64> {
+    Ok(sqlx::query_scalar!(
         "SELECT count(*) FROM messages WHERE to_account_id = $1 AND is_new = 0",
         account_id
     )
     .fetch_one(db)
-    .await
-    .unwrap_or_default()
+    .await?
+    .unwrap_or_default())
 }
 
-async fn get_friends_count(db: &PgPool, account_id: i32) -> Option<i64> {
-    sqlx::query_scalar!(
+async fn get_friends_count(db: &PgPool, account_id: i32) -> Result<i64> {
+    Ok(sqlx::query_scalar!(
         r#"
         SELECT count(*)
         FROM friendships
@@ -353,11 +326,11 @@ async fn get_friends_count(db: &PgPool, account_id: i32) -> Option<i64> {
         account_id
     )
     .fetch_one(db)
-    .await
-    .unwrap_or_default()
+    .await?
+    .unwrap_or_default())
 }
 
-async fn get_friend_state(db: &PgPool, account_id: i32, target_account_id: i32) -> i32 {
+async fn get_friend_state(db: &PgPool, account_id: i32, target_account_id: i32) -> Result<i32> {
     let incoming_req = sqlx::query_scalar!(
         r#"
         SELECT ID FROM friend_requests WHERE account_id = $1 AND to_account_id = $2
@@ -366,11 +339,10 @@ async fn get_friend_state(db: &PgPool, account_id: i32, target_account_id: i32)
         target_account_id
     )
     .fetch_optional(db)
-    .await
-    .unwrap_or(None);
+    .await?;
 
     if incoming_req.is_some() {
-        return 3;
+        return Ok(3);
     }
 
     let outgoing_req = sqlx::query_scalar!(
@@ -381,12 +353,11 @@ async fn get_friend_state(db: &PgPool, account_id: i32, target_account_id: i32)
         account_id
     )
     .fetch_one(db)
-    .await
-    .unwrap_or_default()
-    .unwrap();
+    .await?
+    .unwrap_or_default();
 
     if outgoing_req > 0 {
-        return 4;
+        return Ok(4);
     }
 
     let is_friend = sqlx::query_scalar!(
@@ -399,15 +370,14 @@ async fn get_friend_state(db: &PgPool, account_id: i32, target_account_id: i32)
         target_account_id
     )
     .fetch_one(db)
-    .await
-    .unwrap_or_default()
-    .unwrap();
+    .await?
+    .unwrap_or_default();
 
     if is_friend > 0 {
-        return 1;
+        return Ok(1);
     }
 
-    0
+    Ok(0)
 }
 
 fn sanitize_youtube(youtube: &str) -> String {
@@ -437,18 +407,50 @@ fn sanitize_social(handle: &str) -> String {
 
 async fn update_user_scores(
     Extension(db): Extension<PgPool>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    Form(data): Form<UpdateRequest>,
-) -> Response {
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Authenticated {
+        account,
+        user,
+        form: data,
+    }: Authenticated<UpdateRequest>,
+) -> Result<Response> {
     let upload_date = chrono::Utc::now().timestamp();
 
-    let old_stats = sqlx::query!("SELECT * FROM users WHERE user_id = $1", data.account_id)
-        .fetch_optional(&db)
-        .await
-        .unwrap();
+    // A backup made just now shows what the player really has, anything reported on top of
+    // that is most likely made up. Only logged, since the game can send the two in any order.
+    if let Some(backup) = utilities::database::get_save_versions(&db, account.account_id)
+        .await?
+        .first()
+        && upload_date - (backup.created_at as i64) < BACKUP_CHECK_WINDOW
+        // Saves that couldn't be read are kept without stats, there's nothing to compare to
+        && SaveStats::from(backup) != SaveStats::default()
+    {
+        let reported = SaveStats {
+            stars: data.stars,
+            moons: data.moons,
+            diamonds: data.diamonds,
+            demons: data.demons,
+            coins: data.coins,
+            user_coins: data.user_coins,
+            ..Default::default()
+        };
+        let exceeded = SaveStats::from(backup).exceeded_by(&reported);
+
+        if !exceeded.is_empty() {
+            warn!(
+                "account {} reported more than its backup from {}s ago has: {}",
+                account.account_id,
+                upload_date - backup.created_at as i64,
+                exceeded
+                    .iter()
+                    .map(|(stat, saved, reported)| format!("{stat} {saved} -> {reported}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
 
-    if let Some(_old) = old_stats {
-        let _ = sqlx::query!(
+    sqlx::query!(
             r#"UPDATE users SET
                 game_version = $1, username = $2, coins = $3, secret = $4, stars = $5, demons = $6, icon = $7,
                 color1 = $8, color2 = $9, icon_type = $10, user_coins = $11, special = $12, acc_icon = $13, acc_ship = $14,
@@ -457,7 +459,7 @@ async fn update_user_scores(
                 acc_jetpack = $28, dinfo = $29, sinfo = $30
              WHERE user_id = $31"#,
             data.game_version,
-            data.username,
+            account.username,
             data.coins,
             data.secret,
             data.stars,
@@ -475,7 +477,7 @@ async fn update_user_scores(
             data.accessory_dart,
             data.accessory_robot,
             data.accessory_glow,
-            addr.ip().to_string(),
+            ip.to_string(),
             upload_date as i32,
             data.accessory_spider,
             data.accessory_explosion,
@@ -486,26 +488,23 @@ async fn update_user_scores(
             data.accessory_jetpack,
             data.dinfo.clone(),
             data.sinfo.clone(),
-            data.account_id
+            user.user_id
         )
         .execute(&db)
-        .await;
-
-        return data.account_id.to_string().into_response();
-    }
+        .await?;
 
-    "-1".into_response()
+    Ok(user.user_id.to_string().into_response())
 }
 
 async fn get_user_info(
     Extension(db): Extension<PgPool>,
     Form(data): Form<UserInfoRequest>,
-) -> Response {
+) -> Result<Response> {
     let Some(target) = data.target_account_id else {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     };
     let Some(me) = data.account_id else {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     };
 
     let is_me = me == target;
@@ -518,20 +517,19 @@ async fn get_user_info(
         target
     )
     .fetch_one(&db)
-    .await
+    .await?
     .unwrap_or_default();
 
-    if is_blocked.unwrap() != 0 {
-        return CommonResponse::InvalidRequest.into_response();
+    if is_blocked != 0 {
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
-    let user =
-        match utilities::database::get_user_by_id(&db, data.account_id.unwrap_or_default()).await {
-            Some(user) => user,
-            None => {
-                return CommonResponse::InvalidRequest.into_response();
-            }
-        };
+    let user = match utilities::database::get_user_by_id(&db, target).await? {
+        Some(user) => user,
+        None => {
+            return Ok(CommonResponse::InvalidRequest.into_response());
+        }
+    };
 
     let rank = sqlx::query_scalar!(
         r#"
@@ -541,9 +539,8 @@ async fn get_user_info(
         user.moons
     )
     .fetch_one(&db)
-    .await
-    .unwrap_or_default()
-    .unwrap();
+    .await?
+    .unwrap_or_default();
     let rank = rank + 1;
 
     let account_info = sqlx::query!(
@@ -553,117 +550,92 @@ async fn get_user_info(
         target
     )
     .fetch_optional(&db)
-    .await
-    .unwrap();
-
-    if account_info.is_none() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account_info = account_info.unwrap();
-
-    let account_role_assign = sqlx::query!(
-        "SELECT role_id FROM role_assign WHERE account_id = $1",
-        data.account_id.unwrap_or_default() as i64
-    )
-    .fetch_optional(&db)
-    .await
-    .unwrap();
+    .await?;
 
-    if account_role_assign.is_none() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account_role = sqlx::query!(
-        "SELECT action_request_mod, mod_badge_level FROM roles WHERE role_id = $1",
-        account_role_assign.unwrap().role_id
-    )
-    .fetch_optional(&db)
-    .await
-    .unwrap();
+    let Some(account_info) = account_info else {
+        return Ok(CommonResponse::InvalidRequest.into_response());
+    };
 
-    if account_role.is_none() {
-        return "-1".into_response();
-    }
+    let mod_badge_level = utilities::database::get_user_role(&db, target)
+        .await?
+        .map(|role| role.mod_badge_level)
+        .unwrap_or_default();
 
     let youtube_url = sanitize_youtube(&account_info.youtube_url);
     let twitter = sanitize_social(&account_info.twitter);
     let twitch = sanitize_social(&account_info.twitch);
 
-    let mut response = format!(
-        "1:{}:2:{}:13:{}:17:{}:10:{}:11:{}:51:{}:3:{}:46:{}:52:{}:4:{}:8:{}:18:{}:19:{}:50:{}:20:{}:21:{}:22:{}:23:{}:24:{}:25:{}:26:{}:28:{}:43:{}:48:{}:53:{}:54:{}:30:{}:16:{}:31:{}:44:{}:45:{}:49:{}:55:{}:56:{}:57:{}",
-        user.username,
-        user.user_id,
-        user.coins,
-        user.user_coins,
-        user.color1,
-        user.color2,
-        user.color3,
-        user.stars,
-        user.diamonds,
-        user.moons,
-        user.demons,
-        user.creator_points.round(),
-        account_info.ms,
-        account_info.fr_s,
-        account_info.cs,
-        youtube_url,
-        user.acc_icon,
-        user.acc_ship,
-        user.acc_ball,
-        user.acc_bird,
-        user.acc_dart,
-        user.acc_robot.unwrap(),
-        user.acc_glow,
-        user.acc_spider,
-        user.acc_explosion,
-        user.acc_swing,
-        user.acc_jetpack,
-        rank,
-        user.ext_id,
-        if is_me { 1 } else { 0 },
+    let mut profile = UserProfile {
+        username: user.username,
+        user_id: user.user_id,
+        stars: user.stars,
+        demons: user.demons,
+        creator_points: user.creator_points.round() as i32,
+        color1: user.color1,
+        color2: user.color2,
+        coins: user.coins,
+        account_id: user.ext_id,
+        user_coins: user.user_coins,
+        messages_state: account_info.ms,
+        friend_requests_state: account_info.fr_s,
+        youtube: youtube_url,
+        cube: user.acc_icon,
+        ship: user.acc_ship,
+        ball: user.acc_ball,
+        ufo: user.acc_bird,
+        wave: user.acc_dart,
+        robot: user.acc_robot.unwrap_or_default(),
+        glow: user.acc_glow,
+        is_registered: true,
+        global_rank: rank,
+        friend_state: 0,
+        new_messages: None,
+        new_friend_requests: None,
+        new_friends: None,
+        spider: user.acc_spider,
         twitter,
         twitch,
-        account_role.unwrap().mod_badge_level,
-        user.dinfo.unwrap_or_default(),
-        user.sinfo.unwrap_or_default(),
-        user.pinfo.unwrap_or_default()
-    );
+        diamonds: user.diamonds,
+        explosion: user.acc_explosion,
+        mod_badge: mod_badge_level,
+        comment_history_state: account_info.cs,
+        color3: user.color3,
+        moons: user.moons,
+        swing: user.acc_swing,
+        jetpack: user.acc_jetpack,
+        demons_info: user.dinfo.unwrap_or_default(),
+        classic_levels_info: user.sinfo.unwrap_or_default(),
+        platformer_levels_info: user.pinfo.unwrap_or_default(),
+    };
 
     if is_me {
-        let friend_requests = get_friend_requests_count(&db, me).await.unwrap_or_default();
-        let messages = get_messages_count(&db, me).await.unwrap_or_default();
-        let friends = get_friends_count(&db, me).await.unwrap_or_default();
-        response.push_str(&format!(
-            ":38:{}:39:{}:40:{}:",
-            messages, friend_requests, friends
-        ));
+        profile.new_messages = Some(get_messages_count(&db, me).await?);
+        profile.new_friend_requests = Some(get_friend_requests_count(&db, me).await?);
+        profile.new_friends = Some(get_friends_count(&db, me).await?);
     } else {
-        let friend_state = get_friend_state(&db, me, target).await;
-        response.push_str(&format!(":31:{}:", friend_state));
+        profile.friend_state = get_friend_state(&db, me, target).await?;
     }
 
-    response.push_str("29:1");
-    response.into_response()
+    Ok(profile.encode().into_response())
 }
 
 async fn get_user_comments(
     Extension(db): Extension<PgPool>,
     Form(data): Form<UserCommentsRequest>,
-) -> Response {
+) -> Result<Response> {
     let Some(account_id) = data.account_id else {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     };
     let Some(page) = data.page else {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     };
 
     let offset = (page * 10) as i64;
 
-    let user = match utilities::database::get_user_by_id(&db, account_id).await {
+    let user = match utilities::database::get_user_by_id(&db, account_id).await? {
         Some(user) => user,
         None => {
-            return "#0:0:0".into_response();
+            return Ok("#0:0:0".into_response());
         }
     };
 
@@ -671,78 +643,69 @@ async fn get_user_comments(
         SELECT comment, user_id, likes, is_spam, comment_id, timestamp FROM acc_comments WHERE user_id = $1 ORDER BY timestamp DESC LIMIT 10 OFFSET $2
     "#, user.user_id, offset)
         .fetch_all(&db)
-        .await
-        .unwrap();
+        .await?;
 
     if comments.is_empty() {
-        return "#0:0:0".into_response();
+        return Ok("#0:0:0".into_response());
     }
 
-    let mut comment_string = String::new();
-
     let comment_count: Option<i64> = sqlx::query_scalar!(
         r#"SELECT COUNT(*) FROM acc_comments WHERE user_id = $1"#,
         user.user_id
     )
     .fetch_one(&db)
-    .await
-    .unwrap_or_default();
-
-    for comment in comments {
-        let comment_date = utilities::make_time(comment.timestamp as i64);
-
-        let _ = write!(
-            comment_string,
-            "2~{}~3~{}~4~{}~5~0~7~{}~9~{}~6~{}|",
-            comment.comment,
-            comment.user_id,
-            comment.likes,
-            comment.is_spam,
-            comment_date,
-            comment.comment_id
-        );
-    }
-
-    if comment_string.ends_with('|') {
-        comment_string.pop();
-    }
-
-    format!(
-        "{comment_string}\n#{}:{}:10",
+    .await?;
+
+    let comments = comments
+        .into_iter()
+        .map(|comment| AccountComment {
+            comment: comment.comment,
+            user_id: comment.user_id,
+            likes: comment.likes,
+            dislikes: 0,
+            comment_id: comment.comment_id,
+            is_spam: comment.is_spam,
+            age: utilities::make_time(comment.timestamp as i64),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(format!(
+        "{}\n#{}:{}:10",
+        wire::encode_list(&comments),
         comment_count.unwrap_or_default(),
         offset
     )
-    .into_response()
+    .into_response())
 }
 
 async fn add_user_comment(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<PostCommentRequest>,
-) -> Response {
-    let Some(account_id) = data.account_id else {
-        return CommonResponse::InvalidRequest.into_response();
-    };
+    Authenticated {
+        account,
+        user,
+        form: data,
+    }: Authenticated<PostCommentRequest>,
+) -> Result<Response> {
     let Some(comment) = data.comment.as_ref() else {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     };
 
     let decoded_comment = crypto::decode_base64(comment);
 
     if decoded_comment.len() > 140 {
-        return format!(
+        return Ok(format!(
             "temp_0_You cannot post account comments above 140 characters! (your's {})",
             decoded_comment.len()
         )
-        .into_response();
+        .into_response());
     }
 
     let username = data.username.clone().unwrap_or_default();
-    let user = match utilities::database::get_user_by_id(&db, account_id).await {
-        Some(user) => user,
-        None => {
-            return "#0:0:0".into_response();
-        }
-    };
+    if let Some(ban) =
+        utilities::database::get_active_ban(&db, account.account_id, BanScope::Comment).await?
+    {
+        return Ok(CommentsResponse::from(ban).into_response());
+    }
 
     let timestamp = Utc::now().timestamp();
     sqlx::query!(
@@ -756,27 +719,17 @@ async fn add_user_comment(
         timestamp as i64
     )
     .execute(&db)
-    .await
-    .unwrap();
+    .await?;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn delete_user_comment(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<DeleteCommentRequest>,
-) -> Response {
-    let Some(account_id) = data.account_id else {
-        return CommonResponse::InvalidRequest.into_response();
-    };
-
-    let user = match utilities::database::get_user_by_id(&db, account_id).await {
-        Some(user) => user,
-        None => {
-            return "#0:0:0".into_response();
-        }
-    };
-
+    Authenticated {
+        user, form: data, ..
+    }: Authenticated<DeleteCommentRequest>,
+) -> Result<Response> {
     let comment = sqlx::query!(
         r#"SELECT * FROM acc_comments WHERE comment_id = $1"#,
         data.comment_id
@@ -785,7 +738,7 @@ async fn delete_user_comment(
     .await;
 
     if comment.is_err() {
-        return CommonResponse::InvalidRequest.into_response();
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     sqlx::query!(
@@ -794,17 +747,19 @@ async fn delete_user_comment(
         user.user_id
     )
     .execute(&db)
-    .await
-    .unwrap();
+    .await?;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn update_user_settings(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<UpdateUserSettingsRequest>,
-) -> Response {
-    let account_id = data.account_id.unwrap();
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<UpdateUserSettingsRequest>,
+) -> Result<Response> {
     let youtube = data.youtube;
     let twitter = data.twitter;
     let twitch = data.twitch;
@@ -825,110 +780,64 @@ async fn update_user_settings(
         allow_messages,
         allow_friend_requests,
         show_comments_history,
-        account_id
+        account.account_id
     )
     .execute(&db)
-    .await
-    .unwrap();
+    .await?;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn get_users(
     Extension(db): Extension<PgPool>,
     Form(data): Form<GetUsersRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let users = utilities::database::search_user_by_username(&db, &data.query, 10).await;
-    let mut response = String::new();
+) -> Result<Response> {
+    let users = utilities::database::search_user_by_username(&db, &data.query, 10).await?;
 
     if users.is_empty() {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    for user in &users {
-        response.push_str(&format!(
-            "1:{}:2:{}:13:{}:17:{}:9:{}:10:{}:11:{}:51:{}:14:{}:15:{}:16:{}:3:{}:8:{}:4:{}:46:{}:52:{}|",
-            user.username,
-            user.user_id,
-            user.coins,
-            user.user_coins,
-            user.icon,
-            user.color1,
-            user.color2,
-            user.color3,
-            user.icon_type,
-            user.special,
-            user.ext_id,
-            user.stars,
-            user.creator_points.floor() as i32,
-            user.demons,
-            user.diamonds,
-            user.moons,
-        ));
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
-    let response = response.trim_end_matches("|").to_string();
+    let response = users.iter().map(UserSearchResult::from).collect::<Vec<_>>();
 
-    format!(
+    Ok(format!(
         "{}\n#{}:{}:10",
-        response,
+        wire::encode_list(&response),
         users.len(),
         data.page.unwrap_or_default() * 10
     )
-    .into_response()
+    .into_response())
 }
 
 async fn upload_message(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<UploadMessageRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
+    Authenticated {
+        account,
+        user,
+        form: data,
+    }: Authenticated<UploadMessageRequest>,
+) -> Result<Response> {
+    if account.account_id == data.to_account_id {
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
-    if data.account_id == data.to_account_id {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let sender_account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if sender_account.gjp2.unwrap_or_default() != data.hash {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-    if !sender_account.is_active {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let sender_user = match utilities::database::get_user_by_id(&db, data.account_id).await {
-        Some(user) => user,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
 
-    if sender_user.is_banned == 1 {
-        return CommonResponse::InvalidRequest.into_response();
+    if utilities::database::get_active_ban(&db, account.account_id, BanScope::Messaging)
+        .await?
+        .is_some()
+    {
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     let is_blocked = sqlx::query!(
         "SELECT id FROM blocks WHERE person1 = $1 AND person2 = $2",
         data.to_account_id,
-        data.account_id
+        account.account_id
     )
     .fetch_optional(&db)
-    .await
-    .unwrap();
+    .await?;
 
-    if sender_account.ms == 2 || is_blocked.is_some() {
-        return CommonResponse::InvalidRequest.into_response();
+    if account.ms == 2 || is_blocked.is_some() {
+        return Ok(CommonResponse::InvalidRequest.into_response());
     }
 
     sqlx::query!(
@@ -936,31 +845,20 @@ async fn upload_message(
         INSERT INTO messages (subject, body, acc_id, user_id, username, to_account_id, secret, timestamp)
         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         "#,
-        data.subject, data.body, data.account_id, sender_user.user_id, sender_user.username, data.to_account_id, data.secret, chrono::Utc::now().timestamp() as i32
-    ).execute(&db).await.unwrap();
+        data.subject, data.body, account.account_id, user.user_id, user.username, data.to_account_id, data.secret, chrono::Utc::now().timestamp() as i32
+    ).execute(&db).await?;
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn get_messages(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<GetMessagesRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<GetMessagesRequest>,
+) -> Result<Response> {
     let get_sent = data.get_sent.unwrap_or_default();
     let offset = data.page * 10;
 
@@ -969,34 +867,32 @@ async fn get_messages(
             sqlx::query_as!(
                 Message,
                 "SELECT * FROM messages WHERE to_account_id = $1 LIMIT 10 OFFSET $2",
-                data.account_id,
+                account.account_id,
                 offset as i64
             )
             .fetch_all(&db)
-            .await
-            .unwrap()
+            .await?
         }
         1 => {
             sqlx::query_as!(
                 Message,
                 "SELECT * FROM messages WHERE acc_id = $1 LIMIT 10 OFFSET $2",
-                data.account_id,
+                account.account_id,
                 offset as i64
             )
             .fetch_all(&db)
-            .await
-            .unwrap()
+            .await?
         }
         _ => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
     if messages.is_empty() {
-        return "-2".into_response();
+        return Ok("-2".into_response());
     }
 
-    let mut response = String::new();
+    let mut response = Vec::new();
 
     for message in &messages {
         let id = if get_sent == 0 {
@@ -1005,7 +901,7 @@ async fn get_messages(
             message.to_account_id
         };
 
-        let user = match get_user_by_id(&db, id).await {
+        let user = match get_user_by_id(&db, id).await? {
             Some(user) => user,
             None => {
                 tracing::error!(
@@ -1016,95 +912,77 @@ async fn get_messages(
             }
         };
 
-        response.push_str(&format!(
-            "6:{}:3:{}:2:{}:1:{}:4:{}:8:{}:9:{}:7:{}|",
-            user.username,
-            user.user_id,
-            user.ext_id,
-            message.message_id,
-            message.subject,
-            message.is_new,
-            get_sent,
-            utilities::make_time(message.timestamp as i64)
-        ));
+        response.push(MessageObject {
+            message_id: message.message_id,
+            account_id: user.ext_id,
+            user_id: user.user_id,
+            subject: message.subject.clone(),
+            body: None,
+            username: user.username,
+            age: utilities::make_time(message.timestamp as i64),
+            is_read: message.is_new,
+            is_sender: get_sent,
+        });
     }
 
-    let _ = response.trim_end_matches("|");
-    format!("{}#{}:{}:10", response, messages.len(), offset).into_response()
+    Ok(format!(
+        "{}#{}:{}:10",
+        wire::encode_list(&response),
+        messages.len(),
+        offset
+    )
+    .into_response())
 }
 
 async fn download_message(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<DownloadMessageRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<DownloadMessageRequest>,
+) -> Result<Response> {
     let is_sender = data.is_sender.unwrap_or_default();
 
     let message = sqlx::query!(
         "SELECT * FROM messages WHERE message_id = $1 AND (acc_id = $2 OR to_account_id = $2) LIMIT 1",
-        data.message_id, data.account_id
-    ).fetch_one(&db).await.unwrap();
+        data.message_id, account.account_id
+    ).fetch_one(&db).await?;
 
     if is_sender != 1 {
         sqlx::query!(
             "UPDATE messages SET is_new = 1 WHERE message_id = $1 AND to_account_id = $2",
             data.message_id,
-            data.account_id
+            account.account_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
     }
 
-    format!(
-        "6:{}:3:{}:2:{}:1:{}:4:{}:8:{}:9:{}:5:{}:7:{}",
-        message.username,
-        message.acc_id,
-        message.acc_id,
-        message.message_id,
-        message.subject,
-        message.is_new,
+    let message = MessageObject {
+        message_id: message.message_id,
+        account_id: message.acc_id.to_string(),
+        user_id: message.user_id,
+        subject: message.subject,
+        body: Some(message.body),
+        username: message.username,
+        age: utilities::make_time(message.timestamp as i64),
+        is_read: message.is_new,
         is_sender,
-        message.body,
-        utilities::make_time(message.timestamp as i64)
-    )
-    .into_response()
+    };
+
+    Ok(message.encode().into_response())
 }
 
 // TODO: rewrite this if-else condition ladder
 async fn delete_message(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<DeleteMessageRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<DeleteMessageRequest>,
+) -> Result<Response> {
     let messages = data.messages.unwrap_or_default();
     let message_id = data.message_id.unwrap_or_default();
 
@@ -1113,100 +991,83 @@ async fn delete_message(
             .split_terminator(",")
             .collect::<Vec<_>>()
             .iter()
-            .map(|m| m.parse::<i32>().unwrap())
+            .filter_map(|m| m.parse::<i32>().ok())
             .collect::<Vec<_>>();
 
         sqlx::query!(
             "DELETE FROM messages WHERE message_id = ANY($1) AND acc_id = $2",
             &message_ids,
-            data.account_id
+            account.account_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
 
         sqlx::query!(
             "DELETE FROM messages WHERE message_id = ANY($1) AND to_account_id = $2",
             &message_ids,
-            data.account_id
+            account.account_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
     } else {
         sqlx::query!(
             "DELETE FROM messages WHERE message_id = $1 AND acc_id = $2",
             message_id,
-            data.account_id
+            account.account_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
 
         sqlx::query!(
             "DELETE FROM messages WHERE message_id = $1 AND to_account_id = $2",
             message_id,
-            data.account_id
+            account.account_id
         )
         .execute(&db)
-        .await
-        .unwrap();
+        .await?;
     }
 
-    CommonResponse::Success.into_response()
+    Ok(CommonResponse::Success.into_response())
 }
 
 async fn get_friends(
     Extension(db): Extension<PgPool>,
-    Form(data): Form<GetFriendsRequest>,
-) -> Response {
-    if data.secret != COMMON_SECRET {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
+    Authenticated {
+        account,
+        form: data,
+        ..
+    }: Authenticated<GetFriendsRequest>,
+) -> Result<Response> {
     let get_sent = data.get_sent.unwrap_or_default();
     let offset = data.page * 10;
 
-    let account = match utilities::database::get_account_by_id(&db, data.account_id).await {
-        Some(account) => account,
-        None => {
-            return CommonResponse::InvalidRequest.into_response();
-        }
-    };
-
-    if account.gjp2.unwrap_or_default() != data.hash {
-        return CommonResponse::InvalidRequest.into_response();
-    }
-
     let friend_requests = match data.get_sent.unwrap_or_default() {
         0 => {
             sqlx::query_as!(
                 FriendRequest,
                 "SELECT * FROM friend_requests WHERE to_account_id = $1 LIMIT 10 OFFSET $2",
-                data.account_id,
+                account.account_id,
                 offset as i64
             )
             .fetch_all(&db)
-            .await
-            .unwrap()
+            .await?
         }
         1 => {
             sqlx::query_as!(
                 FriendRequest,
                 "SELECT * FROM friend_requests WHERE account_id = $1 LIMIT 10 OFFSET $2",
-                data.account_id,
+                account.account_id,
                 offset as i64
             )
             .fetch_all(&db)
-            .await
-            .unwrap()
+            .await?
         }
         _ => {
-            return CommonResponse::InvalidRequest.into_response();
+            return Ok(CommonResponse::InvalidRequest.into_response());
         }
     };
 
-    let mut response = String::new();
+    let mut response = Vec::new();
 
     for friend_request in &friend_requests {
         let sender_id = if get_sent == 0 {
@@ -1215,7 +1076,7 @@ async fn get_friends(
             friend_request.to_account_id
         };
 
-        let sender_user = match get_user_by_id(&db, sender_id).await {
+        let sender_user = match get_user_by_id(&db, sender_id).await? {
             Some(user) => user,
             None => {
                 tracing::error!(
@@ -1226,51 +1087,79 @@ async fn get_friends(
             }
         };
 
-        response.push_str(&format!(
-            "1:{}:2:{}:9:{}:10:{}:11:{}:14:{}:15:{}:16:{}:32:{}:35:{}:41:{}:37:{}|",
-            sender_user.username,
-            sender_user.user_id,
-            sender_user.icon,
-            sender_user.color1,
-            sender_user.color2,
-            sender_user.icon_type,
-            sender_user.special,
-            sender_user.ext_id,
-            friend_request.id,
-            friend_request.comment,
-            friend_request.is_new,
-            utilities::make_time(friend_request.upload_date as i64)
-        ))
+        response.push(FriendRequestObject {
+            username: sender_user.username,
+            user_id: sender_user.user_id,
+            icon: sender_user.icon,
+            color1: sender_user.color1,
+            color2: sender_user.color2,
+            icon_type: sender_user.icon_type,
+            special: sender_user.special,
+            account_id: sender_user.ext_id,
+            request_id: friend_request.id,
+            comment: friend_request.comment.clone(),
+            age: utilities::make_time(friend_request.upload_date as i64),
+            is_new: friend_request.is_new,
+        });
     }
 
-    let _ = response.trim_end_matches("|");
-    format!("{}#{}:{}:10", response, friend_requests.len(), offset).into_response()
+    Ok(format!(
+        "{}#{}:{}:10",
+        wire::encode_list(&response),
+        friend_requests.len(),
+        offset
+    )
+    .into_response())
 }
 
 pub fn init() -> Router {
     Router::new()
         .route(
             "/database/getGJAccountComments20.php",
-            post(get_user_comments),
+            post(Secret::Common, get_user_comments),
+        )
+        .route(
+            "/database/uploadGJAccComment20.php",
+            post(Secret::Common, add_user_comment),
+        )
+        .route(
+            "/database/getGJUserInfo20.php",
+            post(Secret::Common, get_user_info),
         )
-        .route("/database/uploadGJAccComment20.php", post(add_user_comment))
-        .route("/database/getGJUserInfo20.php", post(get_user_info))
         .route(
             "/database/updateGJAccSettings20.php",
-            post(update_user_settings),
+            post(Secret::Account, update_user_settings),
         )
         .route(
             "/database/deleteGJAccComment20.php",
-            post(delete_user_comment),
+            post(Secret::Common, delete_user_comment),
         )
         .route(
             "/database/updateGJUserScore22.php",
-            post(update_user_scores),
+            post(Secret::Common, update_user_scores),
+        )
+        .route(
+            "/database/getGJUsers20.php",
+            post(Secret::Common, get_users),
+        )
+        .route(
+            "/database/getGJFriendRequests20.php",
+            post(Secret::Common, get_friends),
+        )
+        .route(
+            "/database/uploadGJMessage20.php",
+            post(Secret::Common, upload_message),
+        )
+        .route(
+            "/database/getGJMessages20.php",
+            post(Secret::Common, get_messages),
+        )
+        .route(
+            "/database/downloadGJMessage20.php",
+            post(Secret::Common, download_message),
+        )
+        .route(
+            "/database/deleteGJMessages20.php",
+            post(Secret::Common, delete_message),
         )
-        .route("/database/getGJUsers20.php", post(get_users))
-        .route("/database/getGJFriendRequests20.php", post(get_friends))
-        .route("/database/uploadGJMessage20.php", post(upload_message))
-        .route("/database/getGJMessages20.php", post(get_messages))
-        .route("/database/downloadGJMessage20.php", post(download_message))
-        .route("/database/deleteGJMessages20.php", post(delete_message))
 }
@@ -1 +1,3 @@
+pub mod admin;
 pub mod database;
+pub mod web;
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension, Router,
+    extract::Query,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use sqlx::PgPool;
+
+use super::{LinkState, SignedLink, escape, invalid_link, make_link, page};
+use crate::{config::Config, types::error::Result, utilities};
+
+// How long an activation link stays valid, in seconds
+const ACTIVATION_LINK_LIFETIME: i64 = 2 * 24 * 60 * 60;
+
+// Sent to new accounts when email verification is enabled
+pub fn activation_link(config: &Config, account_id: i32) -> String {
+    make_link(
+        config,
+        "/accounts/activate",
+        "activate",
+        account_id,
+        ACTIVATION_LINK_LIFETIME,
+        "",
+    )
+}
+
+async fn activate_account(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Query(link): Query<SignedLink>,
+) -> Result<Response> {
+    match link.check(&config, "activate", "") {
+        LinkState::Valid => (),
+        LinkState::Invalid => return Ok(invalid_link().into_response()),
+        LinkState::Expired => {
+            return Ok(page(
+                "Link expired",
+                "<p>This activation link has expired, logging in from the game sends a new one.</p>",
+            )
+            .into_response());
+        }
+    }
+
+    let Some(account) = utilities::database::get_account_by_id(&db, link.id).await? else {
+        return Ok(invalid_link().into_response());
+    };
+
+    if !account.is_active {
+        sqlx::query!(
+            "UPDATE accounts SET is_active = true WHERE account_id = $1",
+            account.account_id
+        )
+        .execute(&db)
+        .await?;
+    }
+
+    Ok(page(
+        "Account activated",
+        &format!(
+            "<p>{} can now log in from the game.</p>",
+            escape(&account.username)
+        ),
+    )
+    .into_response())
+}
+
+pub fn init() -> Router {
+    Router::new().route("/accounts/activate", get(activate_account))
+}
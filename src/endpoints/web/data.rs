@@ -0,0 +1,217 @@
+// Handing players a copy of their data and deleting it on request. Deleting only schedules it,
+// `utilities::deletion` does the rest once `limits.account_deletion_delay` has passed.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Form, Router,
+    http::header,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use tracing::error;
+
+use super::{error_message, input, log_in, page};
+use crate::{
+    config::Config,
+    middleware::ip_ban::ClientIp,
+    types::error::Result,
+    utilities::{
+        self,
+        mail::{Mail, Mailer},
+        storage::{Blob, Storage},
+    },
+};
+
+#[derive(Deserialize, Debug)]
+struct ExportForm {
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum DeleteAction {
+    Delete,
+    Cancel,
+}
+
+#[derive(Deserialize, Debug)]
+struct DeleteForm {
+    username: String,
+    password: String,
+    action: DeleteAction,
+}
+
+fn export_form(error: Option<&str>) -> Html<String> {
+    page(
+        "Download your data",
+        &format!(
+            "<p>Everything kept about your account, its levels, comments and messages, and its saved data as one JSON file.</p>\n{}<form method=\"post\">{}{}<p><button>Download</button></p></form>",
+            error_message(error),
+            input("Username", "username", "text"),
+            input("Password", "password", "password"),
+        ),
+    )
+}
+
+fn delete_form(config: &Config, error: Option<&str>) -> Html<String> {
+    let days = config.limits.account_deletion_delay / 86_400;
+
+    page(
+        "Delete account",
+        &format!(
+            "<p>Your account, comments, messages, scores and saved data are deleted {}. Your levels stay up without your name. Until then, the deletion can be cancelled here.</p>\n{}<form method=\"post\">{}{}<p><button name=\"action\" value=\"delete\">Delete account</button> <button name=\"action\" value=\"cancel\">Cancel deletion</button></p></form>",
+            match days {
+                0 => "within the hour".to_string(),
+                1 => "after a day".to_string(),
+                days => format!("after {days} days"),
+            },
+            error_message(error),
+            input("Username", "username", "text"),
+            input("Password", "password", "password"),
+        ),
+    )
+}
+
+fn format_date(timestamp: i32) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+async fn export_page() -> Html<String> {
+    export_form(None)
+}
+
+async fn export_data(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(storage): Extension<Storage>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(form): Form<ExportForm>,
+) -> Result<Response> {
+    let account = match log_in(&db, &config, ip, &form.username, &form.password).await? {
+        Ok(account) => account,
+        Err(error) => return Ok(export_form(Some(error)).into_response()),
+    };
+
+    let export = utilities::database::get_account_export(&db, account.account_id).await?;
+    let mut export: Value = match serde_json::from_str(&export) {
+        Ok(export) => export,
+        Err(e) => {
+            error!(
+                "couldn't parse export of account {}: {e}",
+                account.account_id
+            );
+            return Ok(export_form(Some("Something went wrong, try again later.")).into_response());
+        }
+    };
+
+    // Level data is kept in storage, deleted levels are moved aside but still belong to the player
+    if let Some(levels) = export["levels"].as_array_mut() {
+        for level in levels {
+            let Some(level_id) = level["level_id"].as_i64().map(|level_id| level_id as i32) else {
+                continue;
+            };
+
+            let data = match storage.get(&Blob::Level(level_id)).await? {
+                Some(data) => Some(data),
+                None => storage.get(&Blob::DeletedLevel(level_id)).await?,
+            };
+            level["level_string"] = data
+                .map(|data| Value::String(String::from_utf8_lossy(&data).into_owned()))
+                .unwrap_or(Value::Null);
+        }
+    }
+
+    export["save"] = utilities::saves::load(&db, &storage, &config, account.account_id)
+        .await?
+        .map(Value::String)
+        .unwrap_or(Value::Null);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"account-{}.json\"",
+                    account.account_id
+                ),
+            ),
+        ],
+        export.to_string(),
+    )
+        .into_response())
+}
+
+async fn delete_page(Extension(config): Extension<Arc<Config>>) -> Html<String> {
+    delete_form(&config, None)
+}
+
+async fn delete_account(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(form): Form<DeleteForm>,
+) -> Result<Response> {
+    let account = match log_in(&db, &config, ip, &form.username, &form.password).await? {
+        Ok(account) => account,
+        Err(error) => return Ok(delete_form(&config, Some(error)).into_response()),
+    };
+
+    if form.action == DeleteAction::Cancel {
+        let cancelled =
+            utilities::database::cancel_account_deletion(&db, account.account_id).await?;
+
+        return Ok(if cancelled {
+            page(
+                "Deletion cancelled",
+                "<p>Your account stays, nothing was deleted.</p>",
+            )
+        } else {
+            delete_form(&config, Some("Your account isn't going to be deleted."))
+        }
+        .into_response());
+    }
+
+    let delete_at = (chrono::Utc::now().timestamp() + config.limits.account_deletion_delay) as i32;
+    utilities::database::schedule_account_deletion(&db, account.account_id, delete_at).await?;
+    let delete_at = utilities::database::get_account_deletion(&db, account.account_id)
+        .await?
+        .unwrap_or(delete_at);
+
+    let mail = Mail {
+        to: account.email.clone(),
+        subject: "Your account is going to be deleted".into(),
+        body: format!(
+            "Hi {},\n\nyour account is going to be deleted on {}. If you change your mind before then, cancel it at:\n{}/accounts/delete\n",
+            account.username,
+            format_date(delete_at),
+            config.public_url.trim_end_matches('/')
+        ),
+    };
+    if let Err(e) = mailer.send(mail).await {
+        error!("couldn't send deletion mail to {}: {e}", account.email);
+    }
+
+    Ok(page(
+        "Deletion scheduled",
+        &format!(
+            "<p>Your account is going to be deleted on {}. Come back here to cancel before then.</p>",
+            format_date(delete_at)
+        ),
+    )
+    .into_response())
+}
+
+pub fn init() -> Router {
+    Router::new()
+        .route("/accounts/export", get(export_page).post(export_data))
+        .route("/accounts/delete", get(delete_page).post(delete_account))
+}
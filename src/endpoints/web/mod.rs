@@ -0,0 +1,170 @@
+pub mod accounts;
+pub mod data;
+pub mod password;
+pub mod saves;
+pub mod username;
+
+use std::net::IpAddr;
+
+use axum::response::Html;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    config::Config,
+    types::{
+        database::{Account, ActionType, BanScope},
+        error::Result,
+    },
+    utilities::{self, credentials},
+};
+
+// Pages opened from links in emails, kept to plain HTML since there's nothing else to serve
+fn page(title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n{body}\n</body>\n</html>\n",
+        title = escape(title),
+    ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn input(label: &str, name: &str, kind: &str) -> String {
+    format!("<p><label>{label}<br><input type=\"{kind}\" name=\"{name}\" required></label></p>")
+}
+
+fn hidden(name: &str, value: &str) -> String {
+    format!(
+        "<input type=\"hidden\" name=\"{name}\" value=\"{}\">",
+        escape(value)
+    )
+}
+
+fn error_message(error: Option<&str>) -> String {
+    error
+        .map(|error| format!("<p><strong>{}</strong></p>", escape(error)))
+        .unwrap_or_default()
+}
+
+fn invalid_link() -> Html<String> {
+    page(
+        "Invalid link",
+        "<p>This link is broken or has already been used, make sure it was copied in full.</p>",
+    )
+}
+
+// Checks a username and password typed into a page, counting towards the same limits and bans as
+// logging in from the game. Errors are meant to be shown on the page.
+async fn log_in(
+    db: &PgPool,
+    config: &Config,
+    ip: IpAddr,
+    username: &str,
+    password: &str,
+) -> Result<std::result::Result<Account, &'static str>> {
+    let account = utilities::database::get_account_by_username(db, username).await?;
+    let account_id = account.as_ref().map(|account| account.account_id);
+    if credentials::login_wait(db, &config.limits, account_id, ip).await? > 0 {
+        return Ok(Err("Too many attempts, try again later."));
+    }
+
+    match account {
+        Some(account)
+            if credentials::check_login(
+                db,
+                &config.secret_key,
+                &account,
+                &credentials::gjp2(password),
+            )
+            .await? =>
+        {
+            // Same as logging in from the game, and also only told after the password
+            if !account.is_active {
+                return Ok(Err(
+                    "This account isn't activated yet, open the link sent to your email first.",
+                ));
+            }
+            if utilities::database::get_active_ban(db, account.account_id, BanScope::Login)
+                .await?
+                .is_some()
+            {
+                return Ok(Err("This account is banned."));
+            }
+
+            Ok(Ok(account))
+        }
+        _ => {
+            utilities::database::log_action(
+                db,
+                account_id.unwrap_or_default(),
+                ActionType::FailedLogin,
+                username,
+                ip,
+            )
+            .await?;
+            Ok(Err("Wrong username or password."))
+        }
+    }
+}
+
+// The parameters of a link sent by email. The signature covers the account, the expiry, what
+// the link is for and whatever it's bound to, so links can't be forged or used for something else.
+#[derive(Deserialize, Debug)]
+struct SignedLink {
+    id: i32,
+    expires: i64,
+    signature: String,
+}
+
+enum LinkState {
+    Valid,
+    Invalid,
+    Expired,
+}
+
+fn link_message(purpose: &str, account_id: i32, expires: i64, binding: &str) -> String {
+    format!("{purpose}:{account_id}:{expires}:{binding}")
+}
+
+fn make_link(
+    config: &Config,
+    path: &str,
+    purpose: &str,
+    account_id: i32,
+    lifetime: i64,
+    binding: &str,
+) -> String {
+    let expires = chrono::Utc::now().timestamp() + lifetime;
+    let signature = utilities::crypto::sign(
+        &config.secret_key,
+        &link_message(purpose, account_id, expires, binding),
+    );
+
+    format!(
+        "{}{path}?id={account_id}&expires={expires}&signature={signature}",
+        config.public_url.trim_end_matches('/')
+    )
+}
+
+impl SignedLink {
+    fn check(&self, config: &Config, purpose: &str, binding: &str) -> LinkState {
+        if !utilities::crypto::verify_signature(
+            &config.secret_key,
+            &link_message(purpose, self.id, self.expires, binding),
+            &self.signature,
+        ) {
+            return LinkState::Invalid;
+        }
+        if self.expires < chrono::Utc::now().timestamp() {
+            return LinkState::Expired;
+        }
+
+        LinkState::Valid
+    }
+}
@@ -0,0 +1,299 @@
+// Changing a forgotten or old password. Saves are stored under the account ID, so they stay
+// with the account no matter how often the password changes.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Form, Router,
+    extract::Query,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use lettre::Address;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::error;
+
+use super::{
+    LinkState, SignedLink, error_message, hidden, input, invalid_link, log_in, make_link, page,
+};
+use crate::{
+    config::Config,
+    middleware::ip_ban::ClientIp,
+    types::{database::ActionType, error::Result},
+    utilities::{
+        self, credentials,
+        mail::{Mail, Mailer},
+    },
+};
+
+// In seconds
+const RESET_LINK_LIFETIME: i64 = 60 * 60;
+// Only one reset mail per account in this time, so the form can't be used to flood an inbox
+const RESET_MAIL_INTERVAL: i64 = 5 * 60;
+
+#[derive(Deserialize, Debug)]
+struct ChangePasswordForm {
+    username: String,
+    old_password: String,
+    password: String,
+    confirm: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResetRequestForm {
+    email: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResetPasswordForm {
+    id: i32,
+    expires: i64,
+    signature: String,
+    password: String,
+    confirm: String,
+}
+
+fn check_new_password(password: &str, confirm: &str) -> Option<String> {
+    if password.len() < credentials::PASSWORD_MIN_LENGTH {
+        return Some(format!(
+            "The new password has to be at least {} characters long.",
+            credentials::PASSWORD_MIN_LENGTH
+        ));
+    }
+    if password != confirm {
+        return Some("The new passwords don't match.".into());
+    }
+
+    None
+}
+
+fn change_form(error: Option<&str>) -> Html<String> {
+    page(
+        "Change password",
+        &format!(
+            "{}<form method=\"post\">{}{}{}{}<p><button>Change password</button></p></form>\n<p><a href=\"/accounts/password/reset\">Forgot your password?</a></p>",
+            error_message(error),
+            input("Username", "username", "text"),
+            input("Current password", "old_password", "password"),
+            input("New password", "password", "password"),
+            input("New password again", "confirm", "password"),
+        ),
+    )
+}
+
+fn reset_form(link: &SignedLink, error: Option<&str>) -> Html<String> {
+    page(
+        "Reset password",
+        &format!(
+            "{}<form method=\"post\">{}{}{}{}{}<p><button>Set password</button></p></form>",
+            error_message(error),
+            hidden("id", &link.id.to_string()),
+            hidden("expires", &link.expires.to_string()),
+            hidden("signature", &link.signature),
+            input("New password", "password", "password"),
+            input("New password again", "confirm", "password"),
+        ),
+    )
+}
+
+fn password_changed() -> Html<String> {
+    page(
+        "Password changed",
+        "<p>Log in again from the game with the new password, your saved data stays with the account.</p>",
+    )
+}
+
+async fn change_password_page() -> Html<String> {
+    change_form(None)
+}
+
+async fn change_password(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(form): Form<ChangePasswordForm>,
+) -> Result<Response> {
+    if let Some(error) = check_new_password(&form.password, &form.confirm) {
+        return Ok(change_form(Some(&error)).into_response());
+    }
+
+    let account = match log_in(&db, &config, ip, &form.username, &form.old_password).await? {
+        Ok(account) => account,
+        Err(error) => return Ok(change_form(Some(error)).into_response()),
+    };
+
+    credentials::set_password(&db, &config.secret_key, account.account_id, &form.password).await?;
+    utilities::database::log_action(
+        &db,
+        account.account_id,
+        ActionType::PasswordChange,
+        &account.username,
+        ip,
+    )
+    .await?;
+
+    Ok(password_changed().into_response())
+}
+
+async fn reset_request_page() -> Html<String> {
+    page(
+        "Reset password",
+        &format!(
+            "<form method=\"post\">{}<p><button>Send reset link</button></p></form>",
+            input("Email", "email", "email"),
+        ),
+    )
+}
+
+async fn request_reset(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(form): Form<ResetRequestForm>,
+) -> Result<Response> {
+    // The same page is shown either way, so it can't be used to find out who's registered
+    let sent = page(
+        "Check your email",
+        "<p>If an account uses that address, a link to reset its password is on the way.</p>",
+    );
+
+    if form.email.parse::<Address>().is_err() {
+        return Ok(sent.into_response());
+    }
+    let Some(account) = utilities::database::get_account_by_email(&db, &form.email).await? else {
+        return Ok(sent.into_response());
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let last_request = utilities::database::get_last_action(
+        &db,
+        account.account_id,
+        ActionType::PasswordResetRequest,
+    )
+    .await?;
+    if last_request.is_some_and(|last| now - (last as i64) < RESET_MAIL_INTERVAL) {
+        return Ok(sent.into_response());
+    }
+
+    // Bound to the current password hash, so the link stops working once it's been used
+    let link = make_link(
+        &config,
+        "/accounts/password/reset/confirm",
+        "reset",
+        account.account_id,
+        RESET_LINK_LIFETIME,
+        &account.password,
+    );
+    let mail = Mail {
+        to: account.email.clone(),
+        subject: "Reset your password".into(),
+        body: format!(
+            "Hi {},\n\nsomeone asked to reset the password of your account, open this link to pick a new one:\n{link}\n\nIt stops working in an hour. If it wasn't you, ignore this email.\n",
+            account.username
+        ),
+    };
+
+    if let Err(e) = mailer.send(mail).await {
+        error!("couldn't send reset mail to {}: {e}", account.email);
+        return Ok(sent.into_response());
+    }
+
+    utilities::database::log_action(
+        &db,
+        account.account_id,
+        ActionType::PasswordResetRequest,
+        &account.username,
+        ip,
+    )
+    .await?;
+
+    Ok(sent.into_response())
+}
+
+async fn reset_page(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Query(link): Query<SignedLink>,
+) -> Result<Response> {
+    let Some(account) = utilities::database::get_account_by_id(&db, link.id).await? else {
+        return Ok(invalid_link().into_response());
+    };
+
+    Ok(match link.check(&config, "reset", &account.password) {
+        LinkState::Valid => reset_form(&link, None),
+        LinkState::Invalid => invalid_link(),
+        LinkState::Expired => page(
+            "Link expired",
+            "<p>This reset link has expired, <a href=\"/accounts/password/reset\">ask for a new one</a>.</p>",
+        ),
+    }
+    .into_response())
+}
+
+async fn reset_password(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(form): Form<ResetPasswordForm>,
+) -> Result<Response> {
+    let link = SignedLink {
+        id: form.id,
+        expires: form.expires,
+        signature: form.signature,
+    };
+
+    let Some(account) = utilities::database::get_account_by_id(&db, link.id).await? else {
+        return Ok(invalid_link().into_response());
+    };
+    if !matches!(
+        link.check(&config, "reset", &account.password),
+        LinkState::Valid
+    ) {
+        return Ok(invalid_link().into_response());
+    }
+
+    if let Some(error) = check_new_password(&form.password, &form.confirm) {
+        return Ok(reset_form(&link, Some(&error)).into_response());
+    }
+
+    credentials::set_password(&db, &config.secret_key, account.account_id, &form.password).await?;
+
+    // The link came through the account's email, that's as good as activating it
+    if !account.is_active {
+        sqlx::query!(
+            "UPDATE accounts SET is_active = true WHERE account_id = $1",
+            account.account_id
+        )
+        .execute(&db)
+        .await?;
+    }
+
+    utilities::database::log_action(
+        &db,
+        account.account_id,
+        ActionType::PasswordChange,
+        &account.username,
+        ip,
+    )
+    .await?;
+
+    Ok(password_changed().into_response())
+}
+
+pub fn init() -> Router {
+    Router::new()
+        .route(
+            "/accounts/password",
+            get(change_password_page).post(change_password),
+        )
+        .route(
+            "/accounts/password/reset",
+            get(reset_request_page).post(request_reset),
+        )
+        .route(
+            "/accounts/password/reset/confirm",
+            get(reset_page).post(reset_password),
+        )
+}
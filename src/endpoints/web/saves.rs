@@ -0,0 +1,122 @@
+// Going back to an earlier backup. The restored one becomes the newest, so the next sync from
+// the game loads it.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Form, Router,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use super::{error_message, escape, input, log_in, page};
+use crate::{
+    config::Config,
+    middleware::ip_ban::ClientIp,
+    types::{database::SaveVersion, error::Result},
+    utilities::{self, storage::Storage},
+};
+
+#[derive(Deserialize, Debug)]
+struct SavesForm {
+    username: String,
+    password: String,
+    // Only lists the backups when missing
+    version: Option<i32>,
+}
+
+fn login_form(error: Option<&str>) -> Html<String> {
+    page(
+        "Restore a backup",
+        &format!(
+            "{}<form method=\"post\">{}{}<p><button>Show backups</button></p></form>",
+            error_message(error),
+            input("Username", "username", "text"),
+            input("Password", "password", "password"),
+        ),
+    )
+}
+
+fn format_size(size: i32) -> String {
+    if size < 1024 {
+        format!("{size} B")
+    } else if size < 1024 * 1024 {
+        format!("{:.1} KB", size as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", size as f64 / (1024.0 * 1024.0))
+    }
+}
+
+fn versions_form(username: &str, versions: &[SaveVersion]) -> Html<String> {
+    let rows = versions
+        .iter()
+        .enumerate()
+        .map(|(i, version)| {
+            format!(
+                "<p><label><input type=\"radio\" name=\"version\" value=\"{}\"{}> {} ({}){}</label></p>",
+                version.version_id,
+                if i == 0 { " checked" } else { "" },
+                chrono::DateTime::from_timestamp(version.created_at as i64, 0)
+                    .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+                    .unwrap_or_default(),
+                format_size(version.size),
+                if i == 0 { ", current" } else { "" },
+            )
+        })
+        .collect::<String>();
+
+    page(
+        "Restore a backup",
+        &format!(
+            "<form method=\"post\"><input type=\"hidden\" name=\"username\" value=\"{}\">{rows}{}<p><button>Restore</button></p></form>",
+            escape(username),
+            input("Password", "password", "password"),
+        ),
+    )
+}
+
+async fn saves_page() -> Html<String> {
+    login_form(None)
+}
+
+async fn restore_save(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(storage): Extension<Storage>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(form): Form<SavesForm>,
+) -> Result<Response> {
+    let account = match log_in(&db, &config, ip, &form.username, &form.password).await? {
+        Ok(account) => account,
+        Err(error) => return Ok(login_form(Some(error)).into_response()),
+    };
+
+    let Some(version_id) = form.version else {
+        let versions = utilities::database::get_save_versions(&db, account.account_id).await?;
+        if versions.is_empty() {
+            return Ok(page(
+                "Restore a backup",
+                "<p>There are no backups yet, back up from the game first.</p>",
+            )
+            .into_response());
+        }
+
+        return Ok(versions_form(&account.username, &versions).into_response());
+    };
+
+    if !utilities::saves::restore(&db, &storage, &config, account.account_id, version_id).await? {
+        return Ok(login_form(Some("That backup doesn't exist anymore.")).into_response());
+    }
+
+    Ok(page(
+        "Backup restored",
+        "<p>Load your data from the game's account settings to get it back.</p>",
+    )
+    .into_response())
+}
+
+pub fn init() -> Router {
+    Router::new().route("/accounts/saves", get(saves_page).post(restore_save))
+}
@@ -0,0 +1,135 @@
+// Renaming an account. The name is copied next to levels, comments and messages, so all of those
+// are renamed together and every change is kept in `username_changes`.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Form, Router,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use super::{error_message, escape, input, log_in, page};
+use crate::{
+    config::Config,
+    middleware::ip_ban::ClientIp,
+    types::error::Result,
+    utilities::{self, cache::LevelSearchCache, credentials},
+};
+
+#[derive(Deserialize, Debug)]
+struct ChangeUsernameForm {
+    username: String,
+    password: String,
+    new_username: String,
+}
+
+fn check_new_username(username: &str) -> Option<&'static str> {
+    if username.len() < credentials::USERNAME_MIN_LENGTH {
+        return Some("The new username has to be at least 3 characters long.");
+    }
+    if username.len() > credentials::USERNAME_MAX_LENGTH {
+        return Some("The new username can't be longer than 20 characters.");
+    }
+
+    None
+}
+
+fn change_form(error: Option<&str>) -> Html<String> {
+    page(
+        "Change username",
+        &format!(
+            "{}<form method=\"post\">{}{}{}<p><button>Change username</button></p></form>",
+            error_message(error),
+            input("Current username", "username", "text"),
+            input("Password", "password", "password"),
+            input("New username", "new_username", "text"),
+        ),
+    )
+}
+
+async fn change_username_page() -> Html<String> {
+    change_form(None)
+}
+
+async fn change_username(
+    Extension(db): Extension<PgPool>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(cache): Extension<LevelSearchCache>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(form): Form<ChangeUsernameForm>,
+) -> Result<Response> {
+    if let Some(error) = check_new_username(&form.new_username) {
+        return Ok(change_form(Some(error)).into_response());
+    }
+
+    let account = match log_in(&db, &config, ip, &form.username, &form.password).await? {
+        Ok(account) => account,
+        Err(error) => return Ok(change_form(Some(error)).into_response()),
+    };
+
+    if form.new_username == account.username {
+        return Ok(change_form(Some("That's already your username.")).into_response());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let last_change =
+        utilities::database::get_last_username_change(&db, account.account_id).await?;
+    if let Some(last) = last_change
+        && now - (last as i64) < config.limits.username_change_interval
+    {
+        let days = (last as i64 + config.limits.username_change_interval - now + 86_399) / 86_400;
+        return Ok(change_form(Some(&format!(
+            "You can change your username again in {days} day{}.",
+            if days == 1 { "" } else { "s" }
+        )))
+        .into_response());
+    }
+
+    // Changing only the case of the own name is fine, anything else can't look like another account
+    if utilities::database::is_username_taken(&db, &form.new_username, account.account_id).await? {
+        return Ok(change_form(Some("That username is already taken.")).into_response());
+    }
+
+    match utilities::database::rename_account(
+        &db,
+        account.account_id,
+        &account.username,
+        &form.new_username,
+    )
+    .await
+    {
+        Ok(true) => (),
+        Ok(false) => {
+            return Ok(
+                change_form(Some("The account was renamed in the meantime.")).into_response(),
+            );
+        }
+        // Someone else took the name between the check and the rename
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Ok(change_form(Some("That username is already taken.")).into_response());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    // Cached level lists still carry the old name
+    cache.invalidate(&db).await;
+
+    Ok(page(
+        "Username changed",
+        &format!(
+            "<p>Log in from the game as {}, your levels, comments and messages moved along with the account.</p>",
+            escape(&form.new_username)
+        ),
+    )
+    .into_response())
+}
+
+pub fn init() -> Router {
+    Router::new().route(
+        "/accounts/username",
+        get(change_username_page).post(change_username),
+    )
+}
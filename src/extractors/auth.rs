@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension,
+    body::{Body, Bytes},
+    extract::{FromRequest, FromRequestParts, Request},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::Form;
+use serde::{Deserialize, de::DeserializeOwned};
+use sqlx::PgPool;
+
+use crate::{
+    config::Config,
+    endpoints::database::user::take_first,
+    types::{
+        database::{Account, BanScope, User},
+        error::Error,
+        response::{CommonResponse, LoginResponse},
+    },
+    utilities,
+};
+
+// The two fields a logged in client sends along with every request. Some endpoints get
+// `accountID` twice, the first one is the sender's.
+#[derive(Deserialize, Debug)]
+struct Credentials {
+    #[serde(default, rename = "accountID", deserialize_with = "take_first")]
+    account_id: Option<i32>,
+    gjp2: Option<String>,
+}
+
+// A form sent by a logged in player. The body is parsed into `T` as usual, while `accountID`
+// and `gjp2` are checked against the database, so banned or inactive accounts never make it
+// to the handler.
+pub struct Authenticated<T> {
+    pub account: Account,
+    pub user: User,
+    pub form: T,
+}
+
+pub enum AuthRejection {
+    // The body couldn't be read or parsed, sent back the same way `Form` would
+    Malformed(Response),
+    WrongCredentials,
+    NotActivated,
+    Banned,
+    Internal(Error),
+}
+
+impl IntoResponse for AuthRejection {
+    fn into_response(self) -> Response {
+        match self {
+            AuthRejection::Malformed(response) => response,
+            AuthRejection::WrongCredentials => CommonResponse::InvalidRequest.into_response(),
+            AuthRejection::NotActivated => LoginResponse::AccountIsNotActivated.into_response(),
+            AuthRejection::Banned => LoginResponse::AccountIsBanned.into_response(),
+            AuthRejection::Internal(e) => e.into_response(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AuthRejection {
+    #[track_caller]
+    fn from(e: sqlx::Error) -> Self {
+        AuthRejection::Internal(e.into())
+    }
+}
+
+impl<S, T> FromRequest<S> for Authenticated<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = AuthRejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = request.into_parts();
+
+        let Extension(db) = Extension::<PgPool>::from_request_parts(&mut parts, state)
+            .await
+            .map_err(|rejection| AuthRejection::Malformed(rejection.into_response()))?;
+        let Extension(config) = Extension::<Arc<Config>>::from_request_parts(&mut parts, state)
+            .await
+            .map_err(|rejection| AuthRejection::Malformed(rejection.into_response()))?;
+
+        // The body can only be read once, so it's buffered and parsed twice
+        let body = Bytes::from_request(Request::from_parts(parts.clone(), body), state)
+            .await
+            .map_err(|rejection| AuthRejection::Malformed(rejection.into_response()))?;
+
+        let Form(form) = Form::<T>::from_request(
+            Request::from_parts(parts.clone(), Body::from(body.clone())),
+            state,
+        )
+        .await
+        .map_err(|rejection| AuthRejection::Malformed(rejection.into_response()))?;
+        let Form(credentials) =
+            Form::<Credentials>::from_request(Request::from_parts(parts, Body::from(body)), state)
+                .await
+                .map_err(|rejection| AuthRejection::Malformed(rejection.into_response()))?;
+
+        let (Some(account_id), Some(hash)) = (credentials.account_id, credentials.gjp2) else {
+            return Err(AuthRejection::WrongCredentials);
+        };
+
+        let Some(account) = utilities::database::get_account_by_id(&db, account_id).await? else {
+            return Err(AuthRejection::WrongCredentials);
+        };
+
+        if !utilities::credentials::check_session(&db, &config.secret_key, &account, &hash).await? {
+            return Err(AuthRejection::WrongCredentials);
+        }
+        if !account.is_active {
+            return Err(AuthRejection::NotActivated);
+        }
+        if utilities::database::get_active_ban(&db, account_id, BanScope::Login)
+            .await?
+            .is_some()
+        {
+            return Err(AuthRejection::Banned);
+        }
+
+        // Accounts get their user on the first login, there's nothing to act as before that
+        let Some(user) = utilities::database::get_user_by_account_id(&db, account_id).await? else {
+            return Err(AuthRejection::WrongCredentials);
+        };
+
+        Ok(Self {
+            account,
+            user,
+            form,
+        })
+    }
+}
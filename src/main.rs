@@ -1,37 +1,121 @@
+mod config;
 mod endpoints;
+mod extractors;
+mod middleware;
 mod types;
 mod utilities;
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 
-use axum::{Extension, Router, routing::get};
+use axum::{Extension, Router, extract::DefaultBodyLimit, routing::get};
 use sqlx::postgres::PgPoolOptions;
+use tracing::error;
+
+use crate::{
+    config::Config,
+    middleware::ip_ban::IpBanList,
+    utilities::{cache::LevelSearchCache, mail::Mailer, storage::Storage},
+};
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt().init();
-    dotenvy::dotenv().unwrap();
+    dotenvy::dotenv().ok();
+
+    let config = match Config::load().and_then(|config| {
+        config.prepare_storage()?;
+        Ok(config)
+    }) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            error!("Configuration error: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mailer = match Mailer::new(&config.mail) {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            error!("Mail configuration error: {e}");
+            std::process::exit(1);
+        }
+    };
 
     let pool = PgPoolOptions::new()
-        .max_connections(1)
-        .connect(env!(
-            "DATABASE_URL",
-            "DATABASE_URL is not set, but .env exists."
-        ))
+        .max_connections(config.max_connections)
+        .connect(&config.database_url)
         .await
         .unwrap();
 
+    let ip_bans = IpBanList::load(&pool, config.features.trust_forwarded_for)
+        .await
+        .unwrap();
+
+    let level_cache = LevelSearchCache::new(config.features.level_cache_notify);
+    if config.features.level_cache_notify {
+        level_cache.listen(&config.database_url).await.unwrap();
+    }
+
+    let storage = match Storage::new(&config.storage, pool.clone()) {
+        Ok(storage) => storage,
+        Err(e) => {
+            error!("Storage configuration error: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // Copies data kept the old ways into the configured backend and exits, see `migrate`
+    if std::env::args().nth(1).as_deref() == Some("migrate-storage") {
+        if let Err(e) = utilities::storage::migrate(&config.storage, &pool, &storage).await {
+            error!("Storage migration failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    utilities::deletion::spawn(pool.clone(), storage.clone(), level_cache.clone());
+    utilities::saves::spawn_rotation(pool.clone(), storage.clone(), config.clone());
+
+    // Form bodies are a bit bigger than the level or save they carry
+    let body_limit = config
+        .limits
+        .max_level_size
+        .max(config.limits.max_save_size)
+        * 2;
+
     let router = Router::new()
         .route("/", get(index))
         .merge(endpoints::database::accounts::init())
         .merge(endpoints::database::user::init())
         .merge(endpoints::database::rewards::init())
         .merge(endpoints::database::levels::init())
+        .merge(endpoints::database::likes::init())
         .merge(endpoints::database::mods::init())
         .merge(endpoints::database::scores::init())
+        .merge(endpoints::admin::leaderboards::init())
+        .merge(endpoints::admin::ips::init())
+        .merge(endpoints::admin::mod_ips::init())
+        .merge(endpoints::admin::roles::init())
+        .merge(endpoints::admin::saves::init())
+        .merge(endpoints::web::accounts::init())
+        .merge(endpoints::web::data::init())
+        .merge(endpoints::web::password::init())
+        .merge(endpoints::web::saves::init())
+        .merge(endpoints::web::username::init())
+        .layer(axum::middleware::from_fn(
+            middleware::ip_ban::reject_banned_ips,
+        ))
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(Extension(ip_bans))
+        .layer(Extension(level_cache))
+        .layer(Extension(mailer))
+        .layer(Extension(storage))
+        .layer(Extension(config.clone()))
         .layer(Extension(pool));
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    let listener = tokio::net::TcpListener::bind(config.listen_address)
+        .await
+        .unwrap();
     axum::serve(
         listener,
         router.into_make_service_with_connect_info::<SocketAddr>(),
@@ -0,0 +1,100 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Request},
+    handler::Handler,
+    middleware::{Next, from_fn},
+    response::{IntoResponse, Response},
+    routing::{self, MethodRouter},
+};
+use axum_extra::extract::Form;
+use serde::Deserialize;
+
+use crate::{
+    endpoints::database::{
+        ACCOUNT_SECRET, COMMON_SECRET, LEVEL_SECRET, MOD_SECRET, user::take_first,
+    },
+    types::response::{CommonResponse, VersionResponse},
+};
+
+// Oldest client the server talks to, 2.207
+const MIN_GAME_VERSION: i32 = 22;
+const MIN_BINARY_VERSION: i32 = 45;
+
+// Which of the game's secrets a route is called with
+#[derive(Clone, Copy, Debug)]
+pub enum Secret {
+    Common,
+    Account,
+    Level,
+    Mod,
+}
+
+impl Secret {
+    fn value(self) -> &'static str {
+        match self {
+            Secret::Common => COMMON_SECRET,
+            Secret::Account => ACCOUNT_SECRET,
+            Secret::Level => LEVEL_SECRET,
+            Secret::Mod => MOD_SECRET,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ClientInfo {
+    secret: Option<String>,
+    #[serde(default, rename = "gameVersion", deserialize_with = "take_first")]
+    game_version: Option<i32>,
+    #[serde(default, rename = "binaryVersion", deserialize_with = "take_first")]
+    binary_version: Option<i32>,
+}
+
+// A game endpoint, the request only reaches the handler if it carries the expected
+// secret and doesn't come from an unsupported version of the game.
+pub fn post<H, T>(secret: Secret, handler: H) -> MethodRouter
+where
+    H: Handler<T, ()>,
+    T: 'static,
+{
+    routing::post(handler).layer(from_fn(move |request, next| {
+        check_client(secret, request, next)
+    }))
+}
+
+async fn check_client(secret: Secret, request: Request, next: Next) -> Response {
+    let (parts, body) = request.into_parts();
+
+    // Handlers read the body again, so it's buffered and put back afterwards
+    let body = match Bytes::from_request(Request::from_parts(parts.clone(), body), &()).await {
+        Ok(body) => body,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let info = match Form::<ClientInfo>::from_request(
+        Request::from_parts(parts.clone(), Body::from(body.clone())),
+        &(),
+    )
+    .await
+    {
+        Ok(Form(info)) => info,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if info.secret.as_deref() != Some(secret.value()) {
+        return CommonResponse::InvalidRequest.into_response();
+    }
+    if is_outdated(info.game_version, info.binary_version) {
+        return VersionResponse::Unsupported.into_response();
+    }
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+// Not every request carries the version, those are let through
+fn is_outdated(game_version: Option<i32>, binary_version: Option<i32>) -> bool {
+    match (game_version, binary_version) {
+        (Some(game), Some(binary)) => (game, binary) < (MIN_GAME_VERSION, MIN_BINARY_VERSION),
+        (Some(game), None) => game < MIN_GAME_VERSION,
+        _ => false,
+    }
+}
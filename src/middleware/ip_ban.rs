@@ -0,0 +1,104 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    Extension,
+    extract::{ConnectInfo, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::{types::response::CommonResponse, utilities::parse_network};
+
+// Address of the client after resolving `X-Forwarded-For`, available to handlers as an extension
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+#[derive(Clone, Default)]
+pub struct IpBanList {
+    networks: Arc<RwLock<Vec<IpNet>>>,
+    trust_forwarded_for: bool,
+}
+
+impl IpBanList {
+    pub async fn load(db: &PgPool, trust_forwarded_for: bool) -> Result<Self, sqlx::Error> {
+        let list = Self {
+            networks: Arc::default(),
+            trust_forwarded_for,
+        };
+        list.reload(db).await?;
+
+        Ok(list)
+    }
+
+    pub async fn reload(&self, db: &PgPool) -> Result<(), sqlx::Error> {
+        let entries = sqlx::query_scalar!("SELECT ip FROM banned_ips")
+            .fetch_all(db)
+            .await?;
+
+        let networks = entries
+            .iter()
+            .filter_map(|entry| {
+                let network = parse_network(entry);
+                if network.is_none() {
+                    warn!("ignoring malformed entry '{}' in 'banned_ips'", entry);
+                }
+
+                network
+            })
+            .collect::<Vec<_>>();
+
+        *self.networks.write().unwrap() = networks;
+
+        Ok(())
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+
+        self.networks
+            .read()
+            .unwrap()
+            .iter()
+            .any(|network| network.contains(&ip))
+    }
+
+    fn resolve(&self, request: &Request, peer: IpAddr) -> IpAddr {
+        if !self.trust_forwarded_for {
+            return peer;
+        }
+
+        // The right-most entry is the one appended by our own reverse proxy,
+        // everything before it is supplied by the client and can be spoofed.
+        request
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|value| value.trim().parse::<IpAddr>().ok())
+            .next_back()
+            .unwrap_or(peer)
+    }
+}
+
+pub async fn reject_banned_ips(
+    Extension(bans): Extension<IpBanList>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let ip = bans.resolve(&request, addr.ip()).to_canonical();
+
+    if bans.is_banned(ip) {
+        return CommonResponse::InvalidRequest.into_response();
+    }
+
+    request.extensions_mut().insert(ClientIp(ip));
+    next.run(request).await
+}
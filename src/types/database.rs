@@ -1,5 +1,7 @@
 use serde::Deserialize;
 
+use crate::utilities::game_save::SaveStats;
+
 #[derive(sqlx::FromRow, Deserialize, Debug, Default)]
 #[allow(unused)]
 pub struct Level {
@@ -55,7 +57,7 @@ pub struct Level {
     pub settings_string: String,
 }
 
-#[derive(sqlx::FromRow, Deserialize, Debug, Default)]
+#[derive(sqlx::FromRow, Deserialize, Debug, Default, Clone)]
 #[allow(unused)]
 pub struct User {
     pub is_registered: i32,
@@ -99,8 +101,6 @@ pub struct User {
     pub chest2_time: i32,
     pub chest1_count: i32,
     pub chest2_count: i32,
-    pub is_banned: i32,
-    pub is_creator_banned: i32,
 }
 
 #[derive(sqlx::FromRow, Deserialize, Debug, Default)]
@@ -126,7 +126,7 @@ pub struct Account {
     pub is_active: bool,
 }
 
-#[derive(sqlx::FromRow, Deserialize, Debug, Default)]
+#[derive(sqlx::FromRow, Deserialize, Debug, Default, Clone)]
 #[allow(unused)]
 pub struct Role {
     pub role_id: i64,
@@ -174,6 +174,21 @@ pub struct Role {
     pub mod_badge_level: i32,
 }
 
+#[derive(sqlx::FromRow, Deserialize, Debug, Default)]
+#[allow(unused)]
+pub struct Song {
+    pub id: i32,
+    pub name: String,
+    pub author_id: i32,
+    pub author_name: String,
+    pub size: String,
+    pub download: String,
+    pub hash: String,
+    pub is_disabled: i32,
+    pub levels_count: i32,
+    pub reupload_time: i32,
+}
+
 #[derive(sqlx::FromRow, Deserialize, Debug, Default)]
 #[allow(unused)]
 pub struct LevelScore {
@@ -215,3 +230,91 @@ pub struct Message {
     pub secret: String,
     pub is_new: i32,
 }
+
+#[derive(sqlx::Type, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(i16)]
+#[allow(unused)]
+pub enum BanScope {
+    #[default]
+    Login = 0,
+    Leaderboard = 1,
+    Creator = 2,
+    Comment = 3,
+    Messaging = 4,
+}
+
+#[derive(sqlx::FromRow, Deserialize, Debug, Default)]
+#[allow(unused)]
+pub struct Ban {
+    pub ban_id: i32,
+    pub account_id: i32,
+    pub scope: BanScope,
+    pub reason: String,
+    pub issued_by: i32,
+    pub issued_at: i64,
+    pub expires_at: Option<i64>,
+    pub is_revoked: bool,
+}
+
+// Values of the `type` column in 'mod_actions'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+#[allow(unused)]
+pub enum ModActionType {
+    LeaderboardBan = 15,
+    // Types from 100 onwards are specific to this server
+    IpBan = 100,
+    ModIp = 101,
+    RoleCreate = 102,
+    RoleAssign = 103,
+    SaveRestore = 104,
+}
+
+// Values of the `type` column in 'actions'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ActionType {
+    Login = 2,
+    FailedLogin = 6,
+    PasswordChange = 7,
+    PasswordResetRequest = 8,
+}
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct FailedLogins {
+    pub count: i64,
+    // Timestamp of the most recent one
+    pub last: i32,
+}
+
+#[derive(sqlx::FromRow, Debug, Default, Clone)]
+#[allow(unused)]
+pub struct SaveVersion {
+    pub version_id: i32,
+    pub account_id: i32,
+    pub size: i32,
+    pub created_at: i32,
+    pub stars: i32,
+    pub moons: i32,
+    pub diamonds: i32,
+    pub demons: i32,
+    pub orbs: i32,
+    pub completed_levels: i32,
+    pub coins: i32,
+    pub user_coins: i32,
+}
+
+impl From<&SaveVersion> for SaveStats {
+    fn from(version: &SaveVersion) -> Self {
+        Self {
+            stars: version.stars,
+            moons: version.moons,
+            diamonds: version.diamonds,
+            demons: version.demons,
+            orbs: version.orbs,
+            completed_levels: version.completed_levels,
+            coins: version.coins,
+            user_coins: version.user_coins,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{fmt, panic::Location};
+
+use axum::response::{IntoResponse, Response};
+use tracing::error;
+
+use super::response::{CommonResponse, LoginResponse};
+use crate::utilities::storage::StorageError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Anything that makes a handler stop early. Expected failures are sent to the client as-is,
+// internal ones are logged along with where they happened and turn into a plain `-1`.
+pub enum Error {
+    Common(CommonResponse),
+    Login(LoginResponse),
+    Database(sqlx::Error, &'static Location<'static>),
+    Io(std::io::Error, &'static Location<'static>),
+    Storage(StorageError, &'static Location<'static>),
+}
+
+impl From<CommonResponse> for Error {
+    fn from(response: CommonResponse) -> Self {
+        Error::Common(response)
+    }
+}
+
+impl From<LoginResponse> for Error {
+    fn from(response: LoginResponse) -> Self {
+        Error::Login(response)
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    #[track_caller]
+    fn from(e: sqlx::Error) -> Self {
+        Error::Database(e, Location::caller())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    #[track_caller]
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e, Location::caller())
+    }
+}
+
+impl From<StorageError> for Error {
+    #[track_caller]
+    fn from(e: StorageError) -> Self {
+        Error::Storage(e, Location::caller())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Common(_) | Error::Login(_) => write!(f, "rejected request"),
+            Error::Database(e, location) => write!(f, "database error at {location}: {e}"),
+            Error::Io(e, location) => write!(f, "io error at {location}: {e}"),
+            Error::Storage(e, location) => write!(f, "storage error at {location}: {e}"),
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        match self {
+            Error::Common(response) => response.into_response(),
+            Error::Login(response) => response.into_response(),
+            Error::Database(..) | Error::Io(..) | Error::Storage(..) => {
+                error!("{self}");
+                CommonResponse::InvalidRequest.into_response()
+            }
+        }
+    }
+}
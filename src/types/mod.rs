@@ -1,2 +1,4 @@
 pub mod database;
+pub mod error;
+pub mod objects;
 pub mod response;
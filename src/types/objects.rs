@@ -0,0 +1,331 @@
+// Objects the game receives, see `utilities::wire` for how they're written
+
+use crate::{
+    types::database::{Level, SaveVersion, Song, User},
+    utilities::wire::wire_object,
+};
+
+wire_object! {
+    pub struct LevelObject(":") {
+        1 => level_id: i32,
+        2 => name: String,
+        3 => description: String,
+        4 => level_string: Option<String>,
+        5 => version: i32,
+        6 => user_id: i32,
+        8 => difficulty_denominator: i32,
+        9 => difficulty: i32,
+        10 => downloads: i32,
+        11 => set_completes: Option<i32>,
+        12 => audio_track: i32,
+        13 => game_version: i32,
+        14 => likes: i32,
+        15 => length: i32,
+        17 => demon: i32,
+        18 => stars: i32,
+        19 => featured: i32,
+        25 => auto: i32,
+        27 => password: Option<String>,
+        28 => upload_date: Option<String>,
+        29 => update_date: Option<String>,
+        30 => original: i32,
+        31 => two_player: i32,
+        35 => song_id: i32,
+        36 => extra_string: Option<String>,
+        37 => coins: i32,
+        38 => verified_coins: i32,
+        39 => requested_stars: i32,
+        40 => low_detail_mode: Option<bool>,
+        42 => epic: i32,
+        43 => demon_difficulty: i32,
+        45 => objects: i32,
+        46 => editor_time: i32,
+        47 => editor_time_copies: i32,
+    }
+}
+
+// The fields shared by level lists and downloads, the rest is up to the endpoint
+impl From<&Level> for LevelObject {
+    fn from(level: &Level) -> Self {
+        Self {
+            level_id: level.level_id,
+            name: level.level_name.clone(),
+            description: level.level_desc.clone(),
+            version: level.level_version,
+            user_id: level.user_id,
+            difficulty_denominator: 10,
+            difficulty: level.star_difficulty,
+            downloads: level.downloads,
+            audio_track: level.audio_track,
+            game_version: level.game_version,
+            likes: level.likes,
+            length: level.level_length,
+            demon: level.star_demon,
+            stars: level.star_stars,
+            featured: level.star_featured,
+            auto: level.star_auto as i32,
+            original: level.original,
+            two_player: level.two_player,
+            song_id: level.song_id,
+            coins: level.coins,
+            verified_coins: level.star_coins,
+            requested_stars: level.requested_stars,
+            epic: level.star_epic,
+            demon_difficulty: level.star_demon_diff,
+            objects: level.objects,
+            editor_time: level.wt,
+            editor_time_copies: level.wt2,
+            ..Default::default()
+        }
+    }
+}
+
+wire_object! {
+    pub struct SongObject("~|~") {
+        1 => song_id: i32,
+        2 => name: String,
+        3 => artist_id: i32,
+        4 => artist_name: String,
+        5 => size: String,
+        6 => video_id: String,
+        10 => download: String,
+    }
+}
+
+impl From<&Song> for SongObject {
+    fn from(song: &Song) -> Self {
+        Self {
+            song_id: song.id,
+            name: song.name.clone(),
+            artist_id: song.author_id,
+            artist_name: song.author_name.clone(),
+            size: song.size.clone(),
+            video_id: String::new(),
+            download: song.download.clone(),
+        }
+    }
+}
+
+wire_object! {
+    // A row of the global, creator and friends leaderboards
+    pub struct LeaderboardUser(":") {
+        1 => username: String,
+        2 => user_id: i32,
+        3 => stars: i32,
+        4 => demons: i32,
+        6 => rank: i32,
+        7 => account_highlight: String,
+        8 => creator_points: i32,
+        9 => icon: i32,
+        10 => color1: i32,
+        11 => color2: i32,
+        13 => coins: i32,
+        14 => icon_type: i32,
+        15 => special: i32,
+        16 => account_id: String,
+        17 => user_coins: i32,
+        46 => diamonds: i32,
+        51 => color3: i32,
+        52 => moons: i32,
+    }
+}
+
+wire_object! {
+    pub struct UserSearchResult(":") {
+        1 => username: String,
+        2 => user_id: i32,
+        3 => stars: i32,
+        4 => demons: i32,
+        8 => creator_points: i32,
+        9 => icon: i32,
+        10 => color1: i32,
+        11 => color2: i32,
+        13 => coins: i32,
+        14 => icon_type: i32,
+        15 => special: i32,
+        16 => account_id: String,
+        17 => user_coins: i32,
+        46 => diamonds: i32,
+        51 => color3: i32,
+        52 => moons: i32,
+    }
+}
+
+impl From<&User> for UserSearchResult {
+    fn from(user: &User) -> Self {
+        Self {
+            username: user.username.clone(),
+            user_id: user.user_id,
+            stars: user.stars,
+            demons: user.demons,
+            creator_points: user.creator_points.floor() as i32,
+            icon: user.icon,
+            color1: user.color1,
+            color2: user.color2,
+            coins: user.coins,
+            icon_type: user.icon_type,
+            special: user.special,
+            account_id: user.ext_id.clone(),
+            user_coins: user.user_coins,
+            diamonds: user.diamonds,
+            color3: user.color3,
+            moons: user.moons,
+        }
+    }
+}
+
+wire_object! {
+    pub struct LevelScoreUser(":") {
+        1 => username: String,
+        2 => user_id: i32,
+        3 => percent: i32,
+        6 => rank: i32,
+        9 => icon: i32,
+        10 => color1: i32,
+        11 => color2: i32,
+        13 => coins: i32,
+        14 => icon_type: i32,
+        15 => special: i32,
+        16 => account_id: i32,
+        42 => date: String,
+    }
+}
+
+wire_object! {
+    // `getGJUserInfo20`, the counters at the end are only sent to the player themselves
+    pub struct UserProfile(":") {
+        1 => username: String,
+        2 => user_id: i32,
+        3 => stars: i32,
+        4 => demons: i32,
+        8 => creator_points: i32,
+        10 => color1: i32,
+        11 => color2: i32,
+        13 => coins: i32,
+        16 => account_id: String,
+        17 => user_coins: i32,
+        18 => messages_state: i32,
+        19 => friend_requests_state: i32,
+        20 => youtube: String,
+        21 => cube: i32,
+        22 => ship: i32,
+        23 => ball: i32,
+        24 => ufo: i32,
+        25 => wave: i32,
+        26 => robot: i32,
+        28 => glow: i32,
+        29 => is_registered: bool,
+        30 => global_rank: i64,
+        31 => friend_state: i32,
+        38 => new_messages: Option<i64>,
+        39 => new_friend_requests: Option<i64>,
+        40 => new_friends: Option<i64>,
+        43 => spider: i32,
+        44 => twitter: String,
+        45 => twitch: String,
+        46 => diamonds: i32,
+        48 => explosion: i32,
+        49 => mod_badge: i32,
+        50 => comment_history_state: i32,
+        51 => color3: i32,
+        52 => moons: i32,
+        53 => swing: i32,
+        54 => jetpack: i32,
+        55 => demons_info: String,
+        56 => classic_levels_info: String,
+        57 => platformer_levels_info: String,
+    }
+}
+
+wire_object! {
+    pub struct LevelComment("~") {
+        1 => level_id: i32,
+        2 => comment: String,
+        3 => user_id: i32,
+        4 => likes: i32,
+        5 => dislikes: i32,
+        6 => comment_id: i32,
+        7 => is_spam: bool,
+        8 => account_id: i32,
+        9 => age: String,
+        10 => percent: i32,
+        11 => mod_badge: i32,
+        12 => color: String,
+    }
+}
+
+wire_object! {
+    // Sent after each level comment, separated from it by `:`
+    pub struct CommentAuthor("~") {
+        1 => username: String,
+        9 => icon: i32,
+        10 => color1: i32,
+        11 => color2: i32,
+        14 => icon_type: i32,
+        15 => glow: i32,
+        16 => account_id: String,
+    }
+}
+
+wire_object! {
+    pub struct AccountComment("~") {
+        2 => comment: String,
+        3 => user_id: i32,
+        4 => likes: i32,
+        5 => dislikes: i32,
+        6 => comment_id: i32,
+        7 => is_spam: i32,
+        9 => age: String,
+    }
+}
+
+wire_object! {
+    // The body is only sent when a message is opened
+    pub struct MessageObject(":") {
+        1 => message_id: i32,
+        2 => account_id: String,
+        3 => user_id: i32,
+        4 => subject: String,
+        5 => body: Option<String>,
+        6 => username: String,
+        7 => age: String,
+        8 => is_read: i32,
+        9 => is_sender: i32,
+    }
+}
+
+wire_object! {
+    pub struct FriendRequestObject(":") {
+        1 => username: String,
+        2 => user_id: i32,
+        9 => icon: i32,
+        10 => color1: i32,
+        11 => color2: i32,
+        14 => icon_type: i32,
+        15 => special: i32,
+        16 => account_id: String,
+        32 => request_id: i32,
+        35 => comment: String,
+        37 => age: String,
+        41 => is_new: bool,
+    }
+}
+
+wire_object! {
+    // Not something the game asks for, listed by `/admin/saves/list`
+    pub struct SaveVersionObject(":") {
+        1 => version_id: i32,
+        2 => size: i32,
+        3 => created_at: i32,
+    }
+}
+
+impl From<&SaveVersion> for SaveVersionObject {
+    fn from(version: &SaveVersion) -> Self {
+        Self {
+            version_id: version.version_id,
+            size: version.size,
+            created_at: version.created_at,
+        }
+    }
+}
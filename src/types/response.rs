@@ -3,6 +3,8 @@ use axum::{
     response::{IntoResponse, Response},
 };
 
+use super::database::Ban;
+
 #[allow(unused)]
 pub enum CommonResponse {
     Success,
@@ -33,6 +35,8 @@ pub enum LoginResponse {
     UsernameIsTooShort,
     AccountIsBanned,
     AccountIsNotActivated,
+    // The game has no message of its own for this, it's shown as a disabled account
+    TooManyAttempts,
 }
 
 #[allow(unused)]
@@ -52,9 +56,17 @@ pub enum LevelUploadResponse {
     FailedToWriteLevel,
 }
 
+pub enum VersionResponse {
+    // The game only shows a generic error, the message is for anyone looking at the traffic
+    Unsupported,
+}
+
 #[allow(unused)]
 pub enum CommentsResponse {
     NothingFound,
+    // Seconds left and reason, shown by the client as a temporary ban
+    TemporarilyBanned(i64, String),
+    PermanentlyBanned,
 }
 
 impl IntoResponse for CommonResponse {
@@ -98,6 +110,8 @@ impl IntoResponse for LoginResponse {
             LoginResponse::UsernameIsTooShort => "-9",
             LoginResponse::AccountIsBanned => "-12",
             LoginResponse::AccountIsNotActivated => "-13",
+            // The game shows "try again later" for it, -12 would tell the player they're banned
+            LoginResponse::TooManyAttempts => "-1",
         };
 
         (StatusCode::OK, body).into_response()
@@ -131,10 +145,36 @@ impl IntoResponse for LevelUploadResponse {
     }
 }
 
+impl IntoResponse for VersionResponse {
+    fn into_response(self) -> Response {
+        let body = match self {
+            VersionResponse::Unsupported => "This server requires Geometry Dash 2.207 or newer",
+        };
+
+        (StatusCode::UPGRADE_REQUIRED, body).into_response()
+    }
+}
+
+impl From<Ban> for CommentsResponse {
+    fn from(ban: Ban) -> Self {
+        match ban.expires_at {
+            Some(expires_at) => CommentsResponse::TemporarilyBanned(
+                (expires_at - chrono::Utc::now().timestamp()).max(0),
+                ban.reason,
+            ),
+            None => CommentsResponse::PermanentlyBanned,
+        }
+    }
+}
+
 impl IntoResponse for CommentsResponse {
     fn into_response(self) -> Response {
         let body = match self {
-            CommentsResponse::NothingFound => "-1",
+            CommentsResponse::NothingFound => "-1".to_string(),
+            CommentsResponse::TemporarilyBanned(seconds, reason) => {
+                format!("temp_{}_{}", seconds, reason)
+            }
+            CommentsResponse::PermanentlyBanned => "-10".to_string(),
         };
 
         (StatusCode::OK, body).into_response()
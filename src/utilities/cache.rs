@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use sqlx::{PgPool, postgres::PgListener};
+use tracing::{error, warn};
+
+use super::search::LevelSearch;
+
+const CHANNEL: &str = "level_search_cache";
+// Download and like counts change without any level being touched,
+// so entries still go stale on their own after a while.
+const TTL: Duration = Duration::from_secs(60);
+const MAX_ENTRIES: usize = 4096;
+
+type Key = (LevelSearch, i32);
+
+// Finished `getGJLevels21` responses, keyed by the parsed search and the requested page
+#[derive(Clone, Default)]
+pub struct LevelSearchCache {
+    entries: Arc<RwLock<HashMap<Key, (Instant, String)>>>,
+    notify: bool,
+}
+
+impl LevelSearchCache {
+    pub fn new(notify: bool) -> Self {
+        Self {
+            entries: Arc::default(),
+            notify,
+        }
+    }
+
+    pub fn get(&self, search: &LevelSearch, page: i32) -> Option<String> {
+        let entries = self.entries.read().unwrap();
+        let (created_at, response) = entries.get(&(search.clone(), page))?;
+
+        if created_at.elapsed() > TTL {
+            return None;
+        }
+
+        Some(response.clone())
+    }
+
+    pub fn insert(&self, search: LevelSearch, page: i32, response: String) {
+        let mut entries = self.entries.write().unwrap();
+
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, (created_at, _)| created_at.elapsed() <= TTL);
+        }
+        // Only the oldest entry makes room, the rest are still as likely to be asked for again
+        if entries.len() >= MAX_ENTRIES
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, (created_at, _))| *created_at)
+                .map(|(key, _)| key.clone())
+        {
+            entries.remove(&oldest);
+        }
+
+        entries.insert((search, page), (Instant::now(), response));
+    }
+
+    // Should be called whenever a level is uploaded, rated, deleted or has its description changed
+    pub async fn invalidate(&self, db: &PgPool) {
+        self.entries.write().unwrap().clear();
+
+        if !self.notify {
+            return;
+        }
+
+        if let Err(e) = sqlx::query!("SELECT pg_notify($1, '')", CHANNEL)
+            .execute(db)
+            .await
+        {
+            error!("Failed to notify other instances about level changes: {e}");
+        }
+    }
+
+    // Clears the cache whenever another instance invalidates it, uses a dedicated connection
+    pub async fn listen(&self, database_url: &str) -> Result<(), sqlx::Error> {
+        let mut listener = PgListener::connect(database_url).await?;
+        listener.listen(CHANNEL).await?;
+
+        let entries = self.entries.clone();
+        tokio::spawn(async move {
+            loop {
+                let notification = listener.recv().await;
+
+                // Notifications could've been missed while reconnecting, so drop everything either way
+                entries.write().unwrap().clear();
+
+                if let Err(e) = notification {
+                    warn!("Lost connection while listening for level changes: {e}");
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+// Account passwords. The game only ever sends gjp2 (a salted SHA1 of the password) and never the
+// password itself, so gjp2 is what gets hashed with Argon2 under a random salt per account.
+// The requests that follow a login are checked against an HMAC of gjp2 keyed with `secret_key`.
+// The key isn't in the database, so a dump has nothing faster than Argon2 to test guesses against.
+
+use std::net::IpAddr;
+
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
+use rand::Rng;
+use ring::hmac;
+use sqlx::PgPool;
+
+use crate::{
+    config::LimitsConfig,
+    types::database::{Account, FailedLogins},
+    utilities::{self, crypto},
+};
+
+pub struct HashedPassword {
+    pub hash: String,
+    pub salt: String,
+    pub session: String,
+}
+
+// Usernames are limited the same way everywhere an account can pick one, in bytes
+pub const USERNAME_MIN_LENGTH: usize = 3;
+pub const USERNAME_MAX_LENGTH: usize = 20;
+// Same for passwords, wherever one is picked
+pub const PASSWORD_MIN_LENGTH: usize = 6;
+
+// What the client sends in place of the password
+pub fn gjp2(password: &str) -> String {
+    crypto::sha1_salt(&password.to_string(), "mI29fmAnxgTs")
+}
+
+// What's kept in `accounts.gjp2`. The salt goes in as well, so accounts sharing a password don't
+// share a value.
+fn session_hash(secret_key: &str, salt: &str, gjp2: &str) -> String {
+    hex::encode(hmac::sign(
+        &session_key(secret_key),
+        &session_message(salt, gjp2),
+    ))
+}
+
+fn session_key(secret_key: &str) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, secret_key.as_bytes())
+}
+
+fn session_message(salt: &str, gjp2: &str) -> Vec<u8> {
+    format!("session:{salt}:{gjp2}").into_bytes()
+}
+
+fn matches_session(secret_key: &str, account: &Account, gjp2: &str) -> bool {
+    let Some(Ok(session)) = account.gjp2.as_deref().map(hex::decode) else {
+        return false;
+    };
+
+    hmac::verify(
+        &session_key(secret_key),
+        &session_message(&account.salt, gjp2),
+        &session,
+    )
+    .is_ok()
+}
+
+// Whether `gjp2` is the one of the account's last login. Accounts from before salts were per
+// account still have the plain gjp2, they're upgraded by the first request the same way a login
+// would.
+pub async fn check_session(
+    db: &PgPool,
+    secret_key: &str,
+    account: &Account,
+    gjp2: &str,
+) -> Result<bool, sqlx::Error> {
+    if gjp2.is_empty() {
+        return Ok(false);
+    }
+    if account.salt.is_empty() {
+        return check_login(db, secret_key, account, gjp2).await;
+    }
+
+    Ok(matches_session(secret_key, account, gjp2))
+}
+
+pub async fn hash(secret_key: &str, gjp2: &str) -> HashedPassword {
+    let secret_key = secret_key.to_string();
+    let gjp2 = gjp2.to_string();
+    let salt = SaltString::encode_b64(&rand::rng().random::<[u8; 16]>()).unwrap();
+
+    tokio::task::spawn_blocking(move || HashedPassword {
+        hash: Argon2::default()
+            .hash_password(gjp2.as_bytes(), &salt)
+            .unwrap()
+            .to_string(),
+        session: session_hash(&secret_key, salt.as_str(), &gjp2),
+        salt: salt.to_string(),
+    })
+    .await
+    .unwrap()
+}
+
+async fn verify(gjp2: &str, hash: &str) -> bool {
+    let gjp2 = gjp2.to_string();
+    let hash = hash.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(gjp2.as_bytes(), &hash)
+                .is_ok()
+        })
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn store(
+    db: &PgPool,
+    account_id: i32,
+    password: &HashedPassword,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE accounts SET password = $1, salt = $2, gjp2 = $3 WHERE account_id = $4",
+        password.hash,
+        password.salt,
+        password.session,
+        account_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+// Replaces the password, the game has to log in again since its gjp2 changes with it
+pub async fn set_password(
+    db: &PgPool,
+    secret_key: &str,
+    account_id: i32,
+    password: &str,
+) -> Result<(), sqlx::Error> {
+    store(db, account_id, &hash(secret_key, &gjp2(password)).await).await
+}
+
+// Checks a login. Accounts made before salts were per account hashed the password itself under
+// a shared salt, which can't be checked against gjp2, so those fall back to the stored plain
+// gjp2 once and are rehashed on the spot.
+pub async fn check_login(
+    db: &PgPool,
+    secret_key: &str,
+    account: &Account,
+    gjp2: &str,
+) -> Result<bool, sqlx::Error> {
+    if gjp2.is_empty() {
+        return Ok(false);
+    }
+
+    if !account.salt.is_empty() {
+        if !verify(gjp2, &account.password).await {
+            return Ok(false);
+        }
+
+        // Out of date after `secret_key` was replaced
+        if !matches_session(secret_key, account, gjp2) {
+            sqlx::query!(
+                "UPDATE accounts SET gjp2 = $1 WHERE account_id = $2",
+                session_hash(secret_key, &account.salt, gjp2),
+                account.account_id
+            )
+            .execute(db)
+            .await?;
+        }
+
+        return Ok(true);
+    }
+
+    if account.gjp2.as_deref() != Some(gjp2) {
+        return Ok(false);
+    }
+
+    store(db, account.account_id, &hash(secret_key, gjp2).await).await?;
+
+    Ok(true)
+}
+
+// Seconds until the next login may be tried, the longer of the account's and the address' wait.
+// Unknown usernames only count against the address.
+pub async fn login_wait(
+    db: &PgPool,
+    limits: &LimitsConfig,
+    account_id: Option<i32>,
+    ip: IpAddr,
+) -> Result<i64, sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let since = now - limits.login_lockout;
+
+    let mut wait = wait_after(
+        &utilities::database::get_failed_logins_for_ip(db, ip, since).await?,
+        limits.login_attempts_per_ip,
+        limits.login_lockout,
+        now,
+    );
+
+    if let Some(account_id) = account_id {
+        let failures =
+            utilities::database::get_failed_logins_for_account(db, account_id, since).await?;
+        wait = wait.max(wait_after(
+            &failures,
+            limits.login_attempts_per_account,
+            limits.login_lockout,
+            now,
+        ));
+    }
+
+    Ok(wait)
+}
+
+// Each failure doubles the wait, starting at a second, until `attempts` is reached and the
+// full lockout applies
+fn wait_after(failures: &FailedLogins, attempts: u32, lockout: i64, now: i64) -> i64 {
+    if failures.count == 0 {
+        return 0;
+    }
+
+    let delay = if failures.count >= attempts as i64 {
+        lockout
+    } else {
+        (1_i64 << (failures.count - 1).min(30)).min(lockout)
+    };
+
+    (failures.last as i64 + delay - now).max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failures(count: i64, last: i32) -> FailedLogins {
+        FailedLogins { count, last }
+    }
+
+    #[test]
+    fn no_failures_no_wait() {
+        assert_eq!(wait_after(&failures(0, 0), 5, 900, 1000), 0);
+    }
+
+    #[test]
+    fn wait_doubles_with_each_failure() {
+        assert_eq!(wait_after(&failures(1, 1000), 5, 900, 1000), 1);
+        assert_eq!(wait_after(&failures(3, 1000), 5, 900, 1000), 4);
+        assert_eq!(wait_after(&failures(4, 1000), 5, 900, 1002), 6);
+    }
+
+    #[test]
+    fn locked_out_at_the_limit() {
+        assert_eq!(wait_after(&failures(5, 1000), 5, 900, 1000), 900);
+        assert_eq!(wait_after(&failures(5, 1000), 5, 900, 1900), 0);
+    }
+
+    const SECRET_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn session_is_hashed() {
+        let account = Account {
+            salt: "c2FsdHNhbHRzYWx0".into(),
+            gjp2: Some(session_hash(SECRET_KEY, "c2FsdHNhbHRzYWx0", "abc")),
+            ..Default::default()
+        };
+
+        assert!(matches_session(SECRET_KEY, &account, "abc"));
+        assert!(!matches_session(SECRET_KEY, &account, "abd"));
+        assert!(!matches_session(
+            SECRET_KEY,
+            &account,
+            account.gjp2.as_deref().unwrap()
+        ));
+    }
+
+    #[test]
+    fn session_needs_the_secret_key() {
+        let account = Account {
+            salt: "c2FsdHNhbHRzYWx0".into(),
+            gjp2: Some(session_hash(SECRET_KEY, "c2FsdHNhbHRzYWx0", "abc")),
+            ..Default::default()
+        };
+
+        assert!(!matches_session(
+            "fedcba9876543210fedcba9876543210",
+            &account,
+            "abc"
+        ));
+    }
+
+    #[test]
+    fn plain_gjp2_isnt_a_session() {
+        let account = Account {
+            salt: "c2FsdHNhbHRzYWx0".into(),
+            gjp2: Some("abc".into()),
+            ..Default::default()
+        };
+
+        assert!(!matches_session(SECRET_KEY, &account, "abc"));
+    }
+}
@@ -1,10 +1,10 @@
-use std::sync::Arc;
-
-use argon2::{Argon2, PasswordHasher, password_hash::SaltString};
 use base64::{
     Engine,
     engine::general_purpose::{STANDARD, URL_SAFE},
 };
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
 use sha1::Digest as sha1Digest;
 
 pub fn singluar_xor(string: &[u8], key: &[u8]) -> String {
@@ -43,7 +43,9 @@ pub fn sha1_salt(base: &String, salt: &str) -> String {
 pub fn hash_level_string(level_string: &String) -> String {
     let mut lstring: String = String::new();
 
-    for (counter, i) in (0_i16..).zip((0..level_string.len()).step_by(level_string.len() / 40)) {
+    for (counter, i) in
+        (0_i16..).zip((0..level_string.len()).step_by((level_string.len() / 40).max(1)))
+    {
         if counter == 40 {
             break;
         }
@@ -54,22 +56,6 @@ pub fn hash_level_string(level_string: &String) -> String {
     sha1_salt(&lstring, "xI25fpAapCQg")
 }
 
-pub async fn hash_password(password: &str) -> String {
-    let password = password.to_string();
-    let salt = Arc::new(SaltString::from_b64("mI29fmAnxgTs").unwrap());
-
-    tokio::task::spawn_blocking(move || {
-        let salt = Arc::clone(&salt);
-
-        Argon2::default()
-            .hash_password(password.as_bytes(), &*salt)
-            .unwrap()
-            .to_string()
-    })
-    .await
-    .unwrap()
-}
-
 pub fn encode_base64(input: &str) -> String {
     STANDARD.encode(input)
 }
@@ -110,3 +96,61 @@ pub fn generate_checksum(mut values: Vec<String>, key: &str, salt: &str) -> Stri
 
     encode_base64(&xored)
 }
+
+// Signs links the server hands out, so their parameters can't be changed
+pub fn sign(key: &str, message: &str) -> String {
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn verify_signature(key: &str, message: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+
+    mac.verify_slice(&signature).is_ok()
+}
+
+// A 256-bit key written as 64 hex characters
+pub fn decode_key(key: &str) -> Option<[u8; 32]> {
+    hex::decode(key).ok()?.try_into().ok()
+}
+
+fn aead_key(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).unwrap())
+}
+
+// Authenticated encryption for data kept on disk. The random nonce is put in front of the
+// result, `context` isn't stored but has to be the same to decrypt it again.
+pub fn encrypt(key: &[u8; 32], context: &[u8], data: &[u8]) -> Vec<u8> {
+    let nonce = rand::rng().random::<[u8; NONCE_LEN]>();
+    let mut encrypted = data.to_vec();
+    aead_key(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(context),
+            &mut encrypted,
+        )
+        .unwrap();
+
+    [nonce.as_slice(), &encrypted].concat()
+}
+
+// None if the key or context is wrong, or the data was changed
+pub fn decrypt(key: &[u8; 32], context: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let (nonce, encrypted) = data.split_at_checked(NONCE_LEN)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut decrypted = encrypted.to_vec();
+    let length = aead_key(key)
+        .open_in_place(nonce, Aad::from(context), &mut decrypted)
+        .ok()?
+        .len();
+    decrypted.truncate(length);
+
+    Some(decrypted)
+}
@@ -1,8 +1,19 @@
+use std::{collections::HashMap, net::IpAddr};
+
 use sqlx::PgPool;
 
-use crate::types::database::{Account, Level, Role, User};
+use crate::{
+    types::database::{
+        Account, ActionType, Ban, BanScope, FailedLogins, Level, ModActionType, Role, SaveVersion,
+        Song, User,
+    },
+    utilities::game_save::SaveStats,
+};
 
-pub async fn get_account_by_username(db: &PgPool, username: &str) -> Option<Account> {
+pub async fn get_account_by_username(
+    db: &PgPool,
+    username: &str,
+) -> Result<Option<Account>, sqlx::Error> {
     sqlx::query_as!(
         Account,
         "SELECT * FROM accounts WHERE username = $1",
@@ -10,17 +21,45 @@ pub async fn get_account_by_username(db: &PgPool, username: &str) -> Option<Acco
     )
     .fetch_optional(db)
     .await
-    .unwrap()
 }
 
-pub async fn get_account_by_id(db: &PgPool, id: i32) -> Option<Account> {
+// Whether another account already goes by this name, ignoring case so names can't be
+// impersonated by changing a letter's case
+pub async fn is_username_taken(
+    db: &PgPool,
+    username: &str,
+    except_account_id: i32,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM accounts WHERE lower(username) = lower($1) AND account_id != $2) AS "taken!""#,
+        username,
+        except_account_id
+    )
+    .fetch_one(db)
+    .await
+}
+
+// Addresses are compared case-insensitively, they're all the same inbox
+pub async fn get_account_by_email(
+    db: &PgPool,
+    email: &str,
+) -> Result<Option<Account>, sqlx::Error> {
+    sqlx::query_as!(
+        Account,
+        "SELECT * FROM accounts WHERE lower(email) = lower($1) LIMIT 1",
+        email
+    )
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn get_account_by_id(db: &PgPool, id: i32) -> Result<Option<Account>, sqlx::Error> {
     sqlx::query_as!(Account, "SELECT * FROM accounts WHERE account_id = $1", id)
         .fetch_optional(db)
         .await
-        .unwrap()
 }
 
-pub async fn get_user_by_id(db: &PgPool, id: i32) -> Option<User> {
+pub async fn get_user_by_id(db: &PgPool, id: i32) -> Result<Option<User>, sqlx::Error> {
     sqlx::query_as!(
         User,
         "SELECT * FROM users WHERE ext_id = $1 OR user_id = $1::INT",
@@ -28,17 +67,66 @@ pub async fn get_user_by_id(db: &PgPool, id: i32) -> Option<User> {
     )
     .fetch_optional(db)
     .await
-    .unwrap()
 }
 
-// pub async fn get_user_by_username(db: &PgPool, username: &str) -> Option<User> {
+// Only the user registered to the account, `get_user_by_id` also matches a user whose own ID
+// happens to equal `account_id`
+pub async fn get_user_by_account_id(
+    db: &PgPool,
+    account_id: i32,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        "SELECT * FROM users WHERE ext_id = $1 AND is_registered = 1",
+        account_id.to_string()
+    )
+    .fetch_optional(db)
+    .await
+}
+
+// Batched `get_user_by_id`, IDs without a matching user are left out
+pub async fn get_users_by_ids(db: &PgPool, ids: &[i32]) -> Result<HashMap<i32, User>, sqlx::Error> {
+    let ext_ids = ids.iter().map(i32::to_string).collect::<Vec<_>>();
+
+    let users = sqlx::query_as!(
+        User,
+        "SELECT * FROM users WHERE ext_id = ANY($1) OR user_id = ANY($2)",
+        &ext_ids,
+        ids
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut by_id = HashMap::new();
+    for user in &users {
+        if ids.contains(&user.user_id) {
+            by_id.insert(user.user_id, user.clone());
+        }
+    }
+    // An account ID takes precedence over a user ID that happens to be the same number
+    for user in users {
+        if let Ok(id) = user.ext_id.parse::<i32>()
+            && ids.contains(&id)
+        {
+            by_id.insert(id, user);
+        }
+    }
+
+    Ok(by_id)
+}
+
+// pub async fn get_user_by_username(db: &PgPool, username: &str) -> Result<Option<User>, sqlx::Error> {
 //     sqlx::query_as!(User, "SELECT * FROM users WHERE username = $1", username)
 //         .fetch_optional(db)
 //         .await
 //         .unwrap()
 // }
 
-pub async fn search_user_by_username(db: &PgPool, username: &str, limit: i64) -> Vec<User> {
+pub async fn search_user_by_username(
+    db: &PgPool,
+    username: &str,
+    limit: i64,
+) -> Result<Vec<User>, sqlx::Error> {
     sqlx::query_as!(
         User,
         "SELECT * FROM users WHERE username ILIKE $1 LIMIT $2",
@@ -47,10 +135,16 @@ pub async fn search_user_by_username(db: &PgPool, username: &str, limit: i64) ->
     )
     .fetch_all(db)
     .await
-    .unwrap()
 }
 
-pub async fn get_level_by_id(db: &PgPool, id: i32) -> Option<Level> {
+pub async fn get_songs_by_ids(db: &PgPool, ids: &[i32]) -> Result<HashMap<i32, Song>, sqlx::Error> {
+    sqlx::query_as!(Song, "SELECT * FROM songs WHERE id = ANY($1)", ids)
+        .fetch_all(db)
+        .await
+        .map(|songs| songs.into_iter().map(|song| (song.id, song)).collect())
+}
+
+pub async fn get_level_by_id(db: &PgPool, id: i32) -> Result<Option<Level>, sqlx::Error> {
     sqlx::query_as!(
         Level,
         "SELECT * FROM levels WHERE level_id = $1 AND is_deleted = 0",
@@ -58,43 +152,751 @@ pub async fn get_level_by_id(db: &PgPool, id: i32) -> Option<Level> {
     )
     .fetch_optional(db)
     .await
-    .unwrap()
 }
 
-pub async fn get_user_role(db: &PgPool, id: i32) -> Option<Role> {
-    let account = get_account_by_id(db, id).await.unwrap();
+// Roles assigned to the account ordered from the highest priority,
+// accounts without any assignments fall back to the default roles.
+pub async fn get_user_roles(db: &PgPool, id: i32) -> Result<Vec<Role>, sqlx::Error> {
+    let roles = sqlx::query_as!(
+        Role,
+        r#"
+        SELECT roles.* FROM roles
+            INNER JOIN role_assign ON roles.role_id = role_assign.role_id
+        WHERE role_assign.account_id = $1
+        ORDER BY roles.priority DESC, roles.role_id
+    "#,
+        id as i64
+    )
+    .fetch_all(db)
+    .await?;
+
+    if !roles.is_empty() {
+        return Ok(roles);
+    }
+
+    sqlx::query_as!(
+        Role,
+        "SELECT * FROM roles WHERE is_default = 1 ORDER BY priority DESC, role_id"
+    )
+    .fetch_all(db)
+    .await
+}
+
+// Permissions are granted if any of the roles grants them, everything else
+// (badge, comment color, mod IP category) comes from the highest priority role.
+pub fn merge_roles(roles: Vec<Role>) -> Option<Role> {
+    let mut roles = roles.into_iter();
+    let mut merged = roles.next()?;
+
+    macro_rules! grant {
+        ($role:ident, $($field:ident),* $(,)?) => {
+            $(merged.$field = merged.$field.max($role.$field);)*
+        };
+    }
+
+    for role in roles {
+        grant!(
+            role,
+            command_rate,
+            command_feature,
+            command_epic,
+            command_unepic,
+            command_verifycoins,
+            command_daily,
+            command_weekly,
+            command_delete,
+            command_setacc,
+            command_rename_own,
+            command_rename_all,
+            command_pass_own,
+            command_pass_all,
+            command_description_own,
+            command_description_all,
+            command_public_own,
+            command_public_all,
+            command_unlist_own,
+            command_unlist_all,
+            command_sharecp_own,
+            command_sharecp_all,
+            command_song_own,
+            command_song_all,
+            profilecommand_discord,
+            action_rate_demon,
+            action_rate_stars,
+            action_rate_difficulty,
+            action_request_mod,
+            action_suggest_rating,
+            action_delete_comment,
+            tool_leaderboardsban,
+            tool_packcreate,
+            tool_quests_create,
+            tool_modactions,
+            tool_suggestlist,
+            dashboard_mod_tools,
+        );
+    }
+
+    Some(merged)
+}
+
+pub async fn get_user_role(db: &PgPool, id: i32) -> Result<Option<Role>, sqlx::Error> {
+    Ok(merge_roles(get_user_roles(db, id).await?))
+}
+
+// Batched `get_user_role`, fetches the roles of every account in two queries
+pub async fn get_user_roles_by_ids(
+    db: &PgPool,
+    ids: &[i32],
+) -> Result<HashMap<i32, Role>, sqlx::Error> {
+    let account_ids = ids.iter().map(|&id| id as i64).collect::<Vec<_>>();
+
+    let assignments = sqlx::query!(
+        "SELECT account_id, role_id FROM role_assign WHERE account_id = ANY($1)",
+        &account_ids
+    )
+    .fetch_all(db)
+    .await?;
+
+    let roles = sqlx::query_as!(
+        Role,
+        r#"
+        SELECT * FROM roles
+            WHERE is_default = 1 OR role_id IN (SELECT role_id FROM role_assign WHERE account_id = ANY($1))
+        ORDER BY priority DESC, role_id
+    "#,
+        &account_ids
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(ids
+        .iter()
+        .filter_map(|&id| {
+            let assigned = roles
+                .iter()
+                .filter(|role| {
+                    assignments.iter().any(|assignment| {
+                        assignment.account_id == id as i64 && assignment.role_id == role.role_id
+                    })
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let roles = if assigned.is_empty() {
+                roles
+                    .iter()
+                    .filter(|role| role.is_default == 1)
+                    .cloned()
+                    .collect()
+            } else {
+                assigned
+            };
+
+            merge_roles(roles).map(|role| (id, role))
+        })
+        .collect())
+}
+
+// Same as `get_user_role`, but roles tied to moderator IPs only count when the request comes
+// from one registered for their category, so no permission skips the IP check by being merged
+// into another role.
+pub async fn get_privileged_role(
+    db: &PgPool,
+    id: i32,
+    ip: IpAddr,
+) -> Result<Option<Role>, sqlx::Error> {
+    let roles = get_user_roles(db, id).await?;
+
+    let mod_ips = if roles.iter().any(|role| role.modip_category != 0) {
+        sqlx::query!(
+            "SELECT ip, modip_category FROM mod_ips WHERE account_id = $1 AND is_mod = 1",
+            id
+        )
+        .fetch_all(db)
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    let roles = roles
+        .into_iter()
+        .filter(|role| {
+            role.modip_category == 0
+                || mod_ips.iter().any(|entry| {
+                    entry.modip_category == role.modip_category
+                        && super::parse_network(&entry.ip)
+                            .is_some_and(|network| network.contains(&ip))
+                })
+        })
+        .collect();
+
+    Ok(merge_roles(roles))
+}
+
+pub async fn has_free_copy(db: &PgPool, ip: IpAddr) -> Result<bool, sqlx::Error> {
+    let mod_ips = sqlx::query_scalar!(
+        r#"
+        SELECT mod_ips.ip FROM mod_ips
+            INNER JOIN mod_ip_perms ON mod_ips.modip_category = mod_ip_perms.category_id
+        WHERE mod_ip_perms.action_free_copy = 1
+    "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(mod_ips
+        .iter()
+        .filter_map(|entry| super::parse_network(entry))
+        .any(|network| network.contains(&ip)))
+}
 
-    let role_id = sqlx::query_scalar!(
-        "SELECT role_id FROM role_assign WHERE account_id = $1",
-        account.account_id as i64
+pub async fn get_active_ban(
+    db: &PgPool,
+    account_id: i32,
+    scope: BanScope,
+) -> Result<Option<Ban>, sqlx::Error> {
+    sqlx::query_as!(
+        Ban,
+        r#"
+        SELECT ban_id, account_id, scope AS "scope: BanScope", reason, issued_by, issued_at, expires_at, is_revoked
+        FROM bans
+        WHERE account_id = $1
+          AND scope = $2
+          AND is_revoked = FALSE
+          AND (expires_at IS NULL OR expires_at > $3)
+        ORDER BY expires_at DESC NULLS FIRST
+        LIMIT 1
+    "#,
+        account_id,
+        scope as BanScope,
+        chrono::Utc::now().timestamp()
     )
     .fetch_optional(db)
     .await
-    .unwrap()
-    .unwrap_or(0);
+}
 
-    sqlx::query_as!(Role, "SELECT * FROM roles WHERE role_id = $1", role_id)
-        .fetch_optional(db)
-        .await
-        .unwrap()
+pub async fn ban_account(
+    db: &PgPool,
+    account_id: i32,
+    scope: BanScope,
+    reason: &str,
+    expires_at: Option<i64>,
+    issued_by: i32,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO bans (account_id, scope, reason, issued_by, issued_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING ban_id
+    "#,
+        account_id,
+        scope as BanScope,
+        reason,
+        issued_by,
+        chrono::Utc::now().timestamp(),
+        expires_at
+    )
+    .fetch_one(db)
+    .await
+}
+
+pub async fn unban_account(
+    db: &PgPool,
+    account_id: i32,
+    scope: BanScope,
+) -> Result<u64, sqlx::Error> {
+    sqlx::query!(
+        "UPDATE bans SET is_revoked = TRUE WHERE account_id = $1 AND scope = $2 AND is_revoked = FALSE",
+        account_id,
+        scope as BanScope
+    )
+    .execute(db)
+    .await
+    .map(|result| result.rows_affected())
+}
+
+pub async fn log_mod_action(
+    db: &PgPool,
+    account_id: i32,
+    action: ModActionType,
+    value: &str,
+    value2: &str,
+    value3: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO mod_actions (type, value, value2, value3, timestamp, account)
+        VALUES ($1, $2, $3, $4, $5, $6)
+    "#,
+        action as i32,
+        value,
+        value2,
+        value3,
+        chrono::Utc::now().timestamp() as i32,
+        account_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+// `value` holds the username and `value2` the address the action came from
+pub async fn log_action(
+    db: &PgPool,
+    account_id: i32,
+    action: ActionType,
+    username: &str,
+    ip: IpAddr,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO actions (type, value, value2, timestamp, account)
+        VALUES ($1, $2, $3, $4, $5)
+    "#,
+        action as i32,
+        username,
+        ip.to_string(),
+        chrono::Utc::now().timestamp() as i32,
+        account_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
 }
 
-pub async fn ban_user(db: &PgPool, id: i32) {
-    sqlx::query!("UPDATE users SET is_banned = 1 WHERE user_id = $1", id)
-        .execute(db)
+// When the account last did `action`, if ever
+pub async fn get_last_action(
+    db: &PgPool,
+    account_id: i32,
+    action: ActionType,
+) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT max(timestamp) FROM actions WHERE type = $1 AND account = $2",
+        action as i32,
+        account_id
+    )
+    .fetch_one(db)
+    .await
+}
+
+// Renames the account along with every copy of its name kept next to levels, comments and
+// messages, and records the change. Returns false if the account was renamed in the meantime.
+pub async fn rename_account(
+    db: &PgPool,
+    account_id: i32,
+    old_username: &str,
+    new_username: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let renamed = sqlx::query!(
+        "UPDATE accounts SET username = $1 WHERE account_id = $2 AND username = $3",
+        new_username,
+        account_id,
+        old_username
+    )
+    .execute(&mut *tx)
+    .await?;
+    if renamed.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let user_id = sqlx::query_scalar!(
+        "UPDATE users SET username = $1 WHERE ext_id = $2 AND is_registered = 1 RETURNING user_id",
+        new_username,
+        account_id.to_string()
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(user_id) = user_id {
+        sqlx::query!(
+            "UPDATE levels SET username = $1 WHERE user_id = $2",
+            new_username,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE comments SET username = $1 WHERE user_id = $2",
+            new_username,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE acc_comments SET username = $1 WHERE user_id = $2",
+            new_username,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query!(
+        "UPDATE messages SET username = $1 WHERE acc_id = $2",
+        new_username,
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO username_changes (account_id, old_username, new_username, timestamp)
+        VALUES ($1, $2, $3, $4)
+    "#,
+        account_id,
+        old_username,
+        new_username,
+        chrono::Utc::now().timestamp() as i32
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn get_last_username_change(
+    db: &PgPool,
+    account_id: i32,
+) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT max(timestamp) FROM username_changes WHERE account_id = $1",
+        account_id
+    )
+    .fetch_one(db)
+    .await
+}
+
+// Everything stored about an account as a JSON object, without its password hashes. Level data
+// and the save live in files and have to be added separately.
+pub async fn get_account_export(db: &PgPool, account_id: i32) -> Result<String, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT jsonb_build_object(
+            'account', (SELECT to_jsonb(a) - 'password' - 'salt' - 'gjp2' FROM accounts a WHERE account_id = $1),
+            'user', (SELECT to_jsonb(u) FROM users u WHERE ext_id = $2 AND is_registered = 1),
+            'username_changes', (SELECT coalesce(jsonb_agg(to_jsonb(c) ORDER BY id), '[]') FROM username_changes c WHERE account_id = $1),
+            'levels', (SELECT coalesce(jsonb_agg(to_jsonb(l) - 'level_string' ORDER BY level_id), '[]') FROM levels l WHERE ext_id = $2),
+            'comments', (
+                SELECT coalesce(jsonb_agg(to_jsonb(c) ORDER BY comment_id), '[]') FROM comments c
+                WHERE user_id IN (SELECT user_id FROM users WHERE ext_id = $2 AND is_registered = 1)
+            ),
+            'account_comments', (
+                SELECT coalesce(jsonb_agg(to_jsonb(c) ORDER BY comment_id), '[]') FROM acc_comments c
+                WHERE user_id IN (SELECT user_id FROM users WHERE ext_id = $2 AND is_registered = 1)
+            ),
+            'messages_sent', (SELECT coalesce(jsonb_agg(to_jsonb(m) ORDER BY message_id), '[]') FROM messages m WHERE acc_id = $1),
+            'messages_received', (SELECT coalesce(jsonb_agg(to_jsonb(m) ORDER BY message_id), '[]') FROM messages m WHERE to_account_id = $1)
+        )::TEXT AS "export!"
+    "#,
+        account_id,
+        account_id.to_string()
+    )
+    .fetch_one(db)
+    .await
+}
+
+// When the account is going to be deleted, if that was asked for
+pub async fn get_account_deletion(
+    db: &PgPool,
+    account_id: i32,
+) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT delete_at FROM account_deletions WHERE account_id = $1",
+        account_id
+    )
+    .fetch_optional(db)
+    .await
+}
+
+// Asking again keeps the original date
+pub async fn schedule_account_deletion(
+    db: &PgPool,
+    account_id: i32,
+    delete_at: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO account_deletions (account_id, requested_at, delete_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (account_id) DO NOTHING
+    "#,
+        account_id,
+        chrono::Utc::now().timestamp() as i32,
+        delete_at
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn cancel_account_deletion(db: &PgPool, account_id: i32) -> Result<bool, sqlx::Error> {
+    let cancelled = sqlx::query!(
+        "DELETE FROM account_deletions WHERE account_id = $1",
+        account_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(cancelled.rows_affected() > 0)
+}
+
+pub async fn get_due_account_deletions(db: &PgPool, now: i32) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT account_id FROM account_deletions WHERE delete_at <= $1",
+        now
+    )
+    .fetch_all(db)
+    .await
+}
+
+// Removes the account and whatever it wrote or received privately. Levels stay playable for
+// everyone else, so they and the player row they point to are only stripped of the name and stats.
+pub async fn delete_account(
+    db: &PgPool,
+    account_id: i32,
+    placeholder_name: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE users SET
+            username = $1, stars = 0, demons = 0, coins = 0, user_coins = 0, diamonds = 0, moons = 0,
+            orbs = 0, completed_lvls = 0, creator_points = 0, dinfo = '', sinfo = '', pinfo = '',
+            ip = '0.0.0.0'
+        WHERE ext_id = $2 AND is_registered = 1
+        RETURNING user_id
+    "#,
+        placeholder_name,
+        account_id.to_string()
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(user_id) = user_id {
+        sqlx::query!(
+            "UPDATE levels SET username = $1 WHERE user_id = $2",
+            placeholder_name,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM comments WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM acc_comments WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query!(
+        "DELETE FROM messages WHERE acc_id = $1 OR to_account_id = $1",
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM friend_requests WHERE account_id = $1 OR to_account_id = $1",
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM friendships WHERE person1 = $1 OR person2 = $1",
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM blocks WHERE person1 = $1 OR person2 = $1",
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM links WHERE account_id = $1 OR target_account_id = $1",
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM level_scores WHERE account_id = $1", account_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM plat_scores WHERE account_id = $1", account_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM role_assign WHERE account_id = $1",
+        account_id as i64
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM mod_ips WHERE account_id = $1", account_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM actions WHERE account = $1", account_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM username_changes WHERE account_id = $1",
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM account_deletions WHERE account_id = $1",
+        account_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM accounts WHERE account_id = $1", account_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+// Newest first
+pub async fn get_save_versions(
+    db: &PgPool,
+    account_id: i32,
+) -> Result<Vec<SaveVersion>, sqlx::Error> {
+    sqlx::query_as!(
+        SaveVersion,
+        "SELECT * FROM save_versions WHERE account_id = $1 ORDER BY version_id DESC",
+        account_id
+    )
+    .fetch_all(db)
+    .await
+}
+
+// Every account that has at least one backup
+pub async fn get_accounts_with_saves(db: &PgPool) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT DISTINCT account_id FROM save_versions ORDER BY account_id")
+        .fetch_all(db)
         .await
-        .unwrap();
 }
 
-pub async fn get_friends(db: &PgPool, id: i32) -> Vec<i32> {
+pub async fn add_save_version(
+    db: &PgPool,
+    account_id: i32,
+    size: i32,
+    stats: &SaveStats,
+) -> Result<SaveVersion, sqlx::Error> {
+    sqlx::query_as!(
+        SaveVersion,
+        r#"
+        INSERT INTO save_versions (
+            account_id, size, created_at, stars, moons, diamonds, demons, orbs, completed_levels,
+            coins, user_coins
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING *
+    "#,
+        account_id,
+        size,
+        chrono::Utc::now().timestamp() as i32,
+        stats.stars,
+        stats.moons,
+        stats.diamonds,
+        stats.demons,
+        stats.orbs,
+        stats.completed_levels,
+        stats.coins,
+        stats.user_coins
+    )
+    .fetch_one(db)
+    .await
+}
+
+// What the profile shows from the newest save
+pub async fn update_save_stats(
+    db: &PgPool,
+    account_id: i32,
+    stats: &SaveStats,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET orbs = $1, completed_lvls = $2 WHERE ext_id = $3",
+        stats.orbs,
+        stats.completed_levels,
+        account_id.to_string()
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_save_versions(db: &PgPool, version_ids: &[i32]) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM save_versions WHERE version_id = ANY($1)",
+        version_ids
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+// Failed logins from an account since `since` that weren't followed by a successful one
+pub async fn get_failed_logins_for_account(
+    db: &PgPool,
+    account_id: i32,
+    since: i64,
+) -> Result<FailedLogins, sqlx::Error> {
+    sqlx::query_as!(
+        FailedLogins,
+        r#"
+        SELECT count(*) AS "count!", coalesce(max(timestamp), 0) AS "last!" FROM actions
+        WHERE type = $1 AND account = $2 AND timestamp > $3
+          AND timestamp > coalesce((SELECT max(timestamp) FROM actions WHERE type = $4 AND account = $2), 0)
+    "#,
+        ActionType::FailedLogin as i32,
+        account_id,
+        since as i32,
+        ActionType::Login as i32
+    )
+    .fetch_one(db)
+    .await
+}
+
+// Same as above, for any account tried from an address. Logins don't reset this one, or
+// logging into an account of one's own between guesses would keep it at zero.
+pub async fn get_failed_logins_for_ip(
+    db: &PgPool,
+    ip: IpAddr,
+    since: i64,
+) -> Result<FailedLogins, sqlx::Error> {
+    sqlx::query_as!(
+        FailedLogins,
+        r#"
+        SELECT count(*) AS "count!", coalesce(max(timestamp), 0) AS "last!" FROM actions
+        WHERE type = $1 AND value2 = $2 AND timestamp > $3
+    "#,
+        ActionType::FailedLogin as i32,
+        ip.to_string(),
+        since as i32
+    )
+    .fetch_one(db)
+    .await
+}
+
+pub async fn get_friends(db: &PgPool, id: i32) -> Result<Vec<i32>, sqlx::Error> {
     let mut ids = Vec::new();
     let friends = sqlx::query!(
         "SELECT person1, person2 FROM friendships WHERE person1 = $1 OR person2 = $1",
         id
     )
     .fetch_all(db)
-    .await
-    .unwrap();
+    .await?;
 
     for friend in friends {
         if friend.person1 == id {
@@ -104,5 +906,5 @@ pub async fn get_friends(db: &PgPool, id: i32) -> Vec<i32> {
         }
     }
 
-    ids
+    Ok(ids)
 }
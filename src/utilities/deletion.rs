@@ -0,0 +1,65 @@
+// Deletes accounts once the time to cancel has run out. Runs in the background so nothing has
+// to be triggered by hand, the deletion itself is in `database::delete_account`.
+
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{
+    types::error::Result,
+    utilities::{cache::LevelSearchCache, storage::Storage},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// What levels and the leftover player row show in place of the name
+const PLACEHOLDER_NAME: &str = "DeletedUser";
+
+pub fn spawn(db: PgPool, storage: Storage, cache: LevelSearchCache) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = delete_due_accounts(&db, &storage, &cache).await {
+                error!("Failed to delete accounts: {e}");
+            }
+        }
+    });
+}
+
+async fn delete_due_accounts(
+    db: &PgPool,
+    storage: &Storage,
+    cache: &LevelSearchCache,
+) -> Result<()> {
+    let now = chrono::Utc::now().timestamp() as i32;
+    let account_ids = super::database::get_due_account_deletions(db, now).await?;
+    if account_ids.is_empty() {
+        return Ok(());
+    }
+
+    // The saves go first, the deletion stays due until they're gone as well. One account
+    // failing doesn't hold up the others, it's tried again next time.
+    for account_id in account_ids {
+        if let Err(e) = delete_account(db, storage, account_id).await {
+            error!("Failed to delete account {account_id}: {e}");
+            continue;
+        }
+
+        info!("Deleted account {account_id}");
+    }
+
+    // Level lists still carry the old names
+    cache.invalidate(db).await;
+
+    Ok(())
+}
+
+async fn delete_account(db: &PgPool, storage: &Storage, account_id: i32) -> Result<()> {
+    super::saves::remove_all(db, storage, account_id).await?;
+    super::database::delete_account(db, account_id, PLACEHOLDER_NAME).await?;
+
+    Ok(())
+}
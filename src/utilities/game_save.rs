@@ -0,0 +1,474 @@
+// Reading CCGameManager saves sent with backups. The save is a gzipped, URL-safe base64 plist
+// the game writes with its own short tags (`<d>`, `<k>`, `<s>`, ...), the usual plist tags are
+// understood as well. Only the stats in `GS_value` are used for now.
+
+use std::{collections::HashMap, fmt, io::Read};
+
+use flate2::read::GzDecoder;
+
+use crate::utilities::crypto;
+
+// Real saves nest a handful of levels deep and are a few MB once decompressed, anything far
+// beyond that is built to run the parser out of stack or memory
+const MAX_DEPTH: usize = 64;
+const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Dict(HashMap<String, Value>),
+    Array(Vec<Value>),
+    String(String),
+    Integer(i64),
+    Real(f64),
+    Bool(bool),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dict(dict) => dict.get(key),
+            _ => None,
+        }
+    }
+
+    // The game stores most numbers as strings
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            Value::Real(value) => Some(*value as i64),
+            Value::String(value) => value.trim().parse().ok(),
+            Value::Bool(value) => Some(*value as i64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SaveError {
+    Decompress(String),
+    TooLarge,
+    TooDeep,
+    UnexpectedEnd,
+    UnexpectedTag(String),
+    InvalidNumber(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Decompress(e) => write!(f, "failed to decompress save: {e}"),
+            SaveError::TooLarge => write!(
+                f,
+                "save is bigger than {MAX_DECOMPRESSED_SIZE} bytes decompressed"
+            ),
+            SaveError::TooDeep => write!(f, "save is nested deeper than {MAX_DEPTH} levels"),
+            SaveError::UnexpectedEnd => write!(f, "save ends in the middle of a value"),
+            SaveError::UnexpectedTag(tag) => write!(f, "unexpected <{tag}> in save"),
+            SaveError::InvalidNumber(number) => write!(f, "'{number}' isn't a number"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+enum Tag<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Empty(&'a str),
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    // Skips text between tags along with the XML declaration, doctype and comments
+    fn next_tag(&mut self) -> Result<Tag<'a>, SaveError> {
+        loop {
+            let start = self.rest.find('<').ok_or(SaveError::UnexpectedEnd)?;
+            let rest = &self.rest[start + 1..];
+
+            if let Some(comment) = rest.strip_prefix("!--") {
+                let end = comment.find("-->").ok_or(SaveError::UnexpectedEnd)?;
+                self.rest = &comment[end + 3..];
+                continue;
+            }
+
+            let end = rest.find('>').ok_or(SaveError::UnexpectedEnd)?;
+            let tag = rest[..end].trim();
+            self.rest = &rest[end + 1..];
+
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+
+            // Attributes such as `gjver` on `<plist>` don't matter
+            return Ok(if let Some(name) = tag.strip_prefix('/') {
+                Tag::Close(name.trim())
+            } else if let Some(tag) = tag.strip_suffix('/') {
+                Tag::Empty(tag.split_whitespace().next().unwrap_or_default())
+            } else {
+                Tag::Open(tag.split_whitespace().next().unwrap_or_default())
+            });
+        }
+    }
+
+    fn text(&mut self, name: &str) -> Result<String, SaveError> {
+        let close = format!("</{name}>");
+        let end = self.rest.find(&close).ok_or(SaveError::UnexpectedEnd)?;
+        let text = unescape(&self.rest[..end]);
+        self.rest = &self.rest[end + close.len()..];
+
+        Ok(text)
+    }
+
+    // `depth` is how many dicts and arrays the value is in
+    fn value(&mut self, tag: Tag<'a>, depth: usize) -> Result<Value, SaveError> {
+        if depth > MAX_DEPTH {
+            return Err(SaveError::TooDeep);
+        }
+
+        match tag {
+            Tag::Open("d" | "dict") => {
+                let mut dict = HashMap::new();
+
+                loop {
+                    match self.next_tag()? {
+                        Tag::Close(_) => return Ok(Value::Dict(dict)),
+                        Tag::Open(name @ ("k" | "key")) => {
+                            let key = self.text(name)?;
+                            let tag = self.next_tag()?;
+                            dict.insert(key, self.value(tag, depth + 1)?);
+                        }
+                        Tag::Open(name) | Tag::Empty(name) => {
+                            return Err(SaveError::UnexpectedTag(name.to_string()));
+                        }
+                    }
+                }
+            }
+            Tag::Open("a" | "array") => {
+                let mut array = Vec::new();
+
+                loop {
+                    match self.next_tag()? {
+                        Tag::Close(_) => return Ok(Value::Array(array)),
+                        tag => array.push(self.value(tag, depth + 1)?),
+                    }
+                }
+            }
+            Tag::Open(name @ ("s" | "string")) => Ok(Value::String(self.text(name)?)),
+            Tag::Open(name @ ("i" | "integer")) => {
+                let text = self.text(name)?;
+                text.trim()
+                    .parse()
+                    .map(Value::Integer)
+                    .map_err(|_| SaveError::InvalidNumber(text))
+            }
+            Tag::Open(name @ ("r" | "real")) => {
+                let text = self.text(name)?;
+                text.trim()
+                    .parse()
+                    .map(Value::Real)
+                    .map_err(|_| SaveError::InvalidNumber(text))
+            }
+            Tag::Empty("d" | "dict") => Ok(Value::Dict(HashMap::new())),
+            Tag::Empty("a" | "array") => Ok(Value::Array(Vec::new())),
+            Tag::Empty("s" | "string") => Ok(Value::String(String::new())),
+            Tag::Empty("t" | "true") => Ok(Value::Bool(true)),
+            Tag::Empty("f" | "false") => Ok(Value::Bool(false)),
+            Tag::Open(name) | Tag::Close(name) | Tag::Empty(name) => {
+                Err(SaveError::UnexpectedTag(name.to_string()))
+            }
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match decoded {
+            Some(decoded) => {
+                result.push(decoded);
+                rest = &rest[end + 1..];
+            }
+            // Not an entity after all, keep it as written
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+// The value inside `<plist>`, or the first value if there's no `<plist>` around it
+pub fn parse(xml: &str) -> Result<Value, SaveError> {
+    let mut parser = Parser { rest: xml };
+
+    let mut tag = parser.next_tag()?;
+    if let Tag::Open("plist") = tag {
+        tag = parser.next_tag()?;
+    }
+
+    parser.value(tag, 0)
+}
+
+// `saveData` as sent by the game, everything after the first `;` is the level data
+pub fn decode(save_data: &str) -> Result<String, SaveError> {
+    let compressed = save_data.split(';').next().unwrap_or_default();
+    let compressed = crypto::decode_base64_url_raw(compressed);
+
+    // One byte over the limit is enough to tell it's too big
+    let mut xml = String::new();
+    GzDecoder::new(compressed.as_slice())
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_string(&mut xml)
+        .map_err(|e| SaveError::Decompress(e.to_string()))?;
+    if xml.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(SaveError::TooLarge);
+    }
+
+    Ok(xml)
+}
+
+// Keys of `GS_value`
+const STAT_COMPLETED_LEVELS: &str = "4";
+const STAT_DEMONS: &str = "5";
+const STAT_STARS: &str = "6";
+const STAT_COINS: &str = "8";
+const STAT_USER_COINS: &str = "12";
+const STAT_DIAMONDS: &str = "13";
+const STAT_ORBS: &str = "14";
+const STAT_MOONS: &str = "28";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SaveStats {
+    pub stars: i32,
+    pub moons: i32,
+    pub diamonds: i32,
+    pub demons: i32,
+    pub orbs: i32,
+    pub completed_levels: i32,
+    pub coins: i32,
+    pub user_coins: i32,
+}
+
+impl SaveStats {
+    // Stats that were never earned are missing from the save, those are 0
+    pub fn from_save(save: &Value) -> Self {
+        let Some(stats) = save.get("GS_value") else {
+            return Self::default();
+        };
+        let stat = |key| {
+            stats
+                .get(key)
+                .and_then(Value::as_i64)
+                .unwrap_or_default()
+                .clamp(0, i32::MAX as i64) as i32
+        };
+
+        Self {
+            stars: stat(STAT_STARS),
+            moons: stat(STAT_MOONS),
+            diamonds: stat(STAT_DIAMONDS),
+            demons: stat(STAT_DEMONS),
+            orbs: stat(STAT_ORBS),
+            completed_levels: stat(STAT_COMPLETED_LEVELS),
+            coins: stat(STAT_COINS),
+            user_coins: stat(STAT_USER_COINS),
+        }
+    }
+
+    // Decodes and parses a whole backup, this is slow for big saves
+    pub fn from_save_data(save_data: &str) -> Result<Self, SaveError> {
+        Ok(Self::from_save(&parse(&decode(save_data)?)?))
+    }
+
+    // Stats `reported` has more of than this save, as (name, saved, reported). The game sends
+    // the ones that show on the profile with updateGJUserScore, orbs and levels aren't among them.
+    pub fn exceeded_by(&self, reported: &SaveStats) -> Vec<(&'static str, i32, i32)> {
+        [
+            ("stars", self.stars, reported.stars),
+            ("moons", self.moons, reported.moons),
+            ("diamonds", self.diamonds, reported.diamonds),
+            ("demons", self.demons, reported.demons),
+            ("coins", self.coins, reported.coins),
+            ("user_coins", self.user_coins, reported.user_coins),
+        ]
+        .into_iter()
+        .filter(|(_, saved, reported)| reported > saved)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use base64::{Engine, engine::general_purpose::URL_SAFE};
+    use flate2::{Compression, write::GzEncoder};
+
+    use super::*;
+
+    const SAVE: &str = r#"<?xml version="1.0"?><plist version="1.0" gjver="2.0"><dict><k>valueKeeper</k><d><k>gv_0001</k><s>1</s></d><k>GS_value</k><d><k>4</k><s>12</s><k>5</k><s>3</s><k>6</k><s>250</s><k>8</k><s>20</s><k>12</k><s>7</s><k>13</k><s>1500</s><k>14</k><s>9001</s><k>28</k><s>40</s></d><k>playerName</k><s>Tom &amp; Jerry</s><k>bootups</k><i>17</i><k>showSongMarkers</k><t /><k>GLM_03</k><d /></dict></plist>"#;
+
+    #[test]
+    fn parses_game_tags() {
+        let save = parse(SAVE).unwrap();
+
+        assert_eq!(
+            save.get("playerName"),
+            Some(&Value::String("Tom & Jerry".into()))
+        );
+        assert_eq!(save.get("bootups"), Some(&Value::Integer(17)));
+        assert_eq!(save.get("showSongMarkers"), Some(&Value::Bool(true)));
+        assert_eq!(save.get("GLM_03"), Some(&Value::Dict(HashMap::new())));
+    }
+
+    #[test]
+    fn parses_plain_plist_tags() {
+        let save = parse(
+            "<plist><dict><key>a</key><array><integer>1</integer><real>2.5</real><false/></array></dict></plist>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            save.get("a"),
+            Some(&Value::Array(vec![
+                Value::Integer(1),
+                Value::Real(2.5),
+                Value::Bool(false)
+            ]))
+        );
+    }
+
+    #[test]
+    fn reads_stats() {
+        let stats = SaveStats::from_save(&parse(SAVE).unwrap());
+
+        assert_eq!(
+            stats,
+            SaveStats {
+                stars: 250,
+                moons: 40,
+                diamonds: 1500,
+                demons: 3,
+                orbs: 9001,
+                completed_levels: 12,
+                coins: 20,
+                user_coins: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn missing_stats_are_zero() {
+        let stats = SaveStats::from_save(&parse("<plist><dict></dict></plist>").unwrap());
+        assert_eq!(stats, SaveStats::default());
+    }
+
+    #[test]
+    fn rejects_broken_saves() {
+        assert_eq!(
+            parse("<plist><dict><k>a</k><s>1</s>"),
+            Err(SaveError::UnexpectedEnd)
+        );
+        assert_eq!(
+            parse("<plist><dict><s>1</s></dict></plist>"),
+            Err(SaveError::UnexpectedTag("s".into()))
+        );
+        assert_eq!(
+            parse("<plist><i>x</i></plist>"),
+            Err(SaveError::InvalidNumber("x".into()))
+        );
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let nested = |depth| {
+            format!(
+                "<plist>{}<s>1</s>{}</plist>",
+                "<a>".repeat(depth),
+                "</a>".repeat(depth)
+            )
+        };
+
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(parse(&nested(MAX_DEPTH + 1)), Err(SaveError::TooDeep));
+        assert_eq!(parse(&nested(100_000)), Err(SaveError::TooDeep));
+    }
+
+    #[test]
+    fn unescapes_entities() {
+        assert_eq!(unescape("a &lt;b&gt; &#65;&#x42; & c"), "a <b> AB & c");
+    }
+
+    #[test]
+    fn decodes_backups() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(SAVE.as_bytes()).unwrap();
+        let save_data = format!("{};levels", URL_SAFE.encode(encoder.finish().unwrap()));
+
+        assert_eq!(SaveStats::from_save_data(&save_data).unwrap().orbs, 9001);
+        assert!(matches!(
+            SaveStats::from_save_data("not a save;"),
+            Err(SaveError::Decompress(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_gzip_bombs() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        for _ in 0..=MAX_DECOMPRESSED_SIZE / (1024 * 1024) {
+            encoder.write_all(&[b' '; 1024 * 1024]).unwrap();
+        }
+        let save_data = format!("{};", URL_SAFE.encode(encoder.finish().unwrap()));
+
+        assert_eq!(decode(&save_data), Err(SaveError::TooLarge));
+    }
+
+    #[test]
+    fn finds_exceeded_stats() {
+        let saved = SaveStats {
+            stars: 100,
+            coins: 10,
+            ..Default::default()
+        };
+        let reported = SaveStats {
+            stars: 150,
+            coins: 10,
+            ..Default::default()
+        };
+
+        assert_eq!(saved.exceeded_by(&reported), vec![("stars", 100, 150)]);
+        assert!(reported.exceeded_by(&saved).is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+// Outgoing mail. Handlers build a `Mail` and hand it to whichever transport the config picks,
+// the stdout and file ones are there for development and for testing against a local sink.
+
+use std::{fmt, future::Future, path::PathBuf, pin::Pin, sync::Arc};
+
+use crate::{
+    config::{MailConfig, MailTransportKind},
+    utilities,
+};
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::Mailbox,
+    transport::smtp::authentication::Credentials,
+};
+
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    Address(String),
+    Build(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::Address(address) => write!(f, "'{address}' isn't a valid address"),
+            MailError::Build(e) => write!(f, "failed to build message: {e}"),
+            MailError::Smtp(e) => write!(f, "smtp: {e}"),
+            MailError::Io(e) => write!(f, "failed to write message: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+pub type SendFuture<'a> = Pin<Box<dyn Future<Output = Result<(), MailError>> + Send + 'a>>;
+
+pub trait MailTransport: Send + Sync {
+    // Takes the message as it goes over the wire, headers included
+    fn send<'a>(&'a self, message: &'a Message) -> SendFuture<'a>;
+}
+
+// Shared by the handlers through an `Extension`
+#[derive(Clone)]
+pub struct Mailer {
+    from: Mailbox,
+    transport: Arc<dyn MailTransport>,
+}
+
+impl Mailer {
+    pub fn new(config: &MailConfig) -> Result<Self, MailError> {
+        let from = config
+            .from
+            .parse()
+            .map_err(|_| MailError::Address(config.from.clone()))?;
+
+        let transport: Arc<dyn MailTransport> = match config.transport {
+            MailTransportKind::Stdout => Arc::new(StdoutTransport),
+            MailTransportKind::File => Arc::new(FileTransport {
+                directory: config.directory.clone(),
+            }),
+            MailTransportKind::Smtp => Arc::new(SmtpTransport::new(config)?),
+        };
+
+        Ok(Self { from, transport })
+    }
+
+    pub async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        let to = mail
+            .to
+            .parse::<Mailbox>()
+            .map_err(|_| MailError::Address(mail.to.clone()))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(mail.subject)
+            .body(mail.body)
+            .map_err(MailError::Build)?;
+
+        self.transport.send(&message).await
+    }
+}
+
+pub struct StdoutTransport;
+
+impl MailTransport for StdoutTransport {
+    fn send<'a>(&'a self, message: &'a Message) -> SendFuture<'a> {
+        Box::pin(async move {
+            println!("{}", String::from_utf8_lossy(&message.formatted()));
+            Ok(())
+        })
+    }
+}
+
+pub struct FileTransport {
+    directory: PathBuf,
+}
+
+impl MailTransport for FileTransport {
+    fn send<'a>(&'a self, message: &'a Message) -> SendFuture<'a> {
+        Box::pin(async move {
+            let name = format!(
+                "{}-{}.eml",
+                chrono::Utc::now().timestamp(),
+                utilities::rand_ascii(8)
+            );
+
+            tokio::fs::write(self.directory.join(name), message.formatted())
+                .await
+                .map_err(MailError::Io)
+        })
+    }
+}
+
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    fn new(config: &MailConfig) -> Result<Self, MailError> {
+        let mut builder = if config.smtp_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)
+                .map_err(MailError::Smtp)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+        }
+        .port(config.smtp_port);
+
+        if !config.smtp_username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.smtp_username.clone(),
+                config.smtp_password.clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+impl MailTransport for SmtpTransport {
+    fn send<'a>(&'a self, message: &'a Message) -> SendFuture<'a> {
+        Box::pin(async move {
+            self.transport
+                .send(message.clone())
+                .await
+                .map(|_| ())
+                .map_err(MailError::Smtp)
+        })
+    }
+}
@@ -1,8 +1,20 @@
+pub mod cache;
+pub mod credentials;
 pub mod crypto;
 pub mod database;
+pub mod deletion;
+pub mod game_save;
 pub mod gd;
+pub mod mail;
+pub mod saves;
+pub mod search;
+pub mod storage;
+pub mod wire;
+
+use std::net::IpAddr;
 
 use chrono::{DateTime, Utc};
+use ipnet::IpNet;
 use rand::Rng;
 
 pub fn make_time(timestamp: i64) -> String {
@@ -46,3 +58,13 @@ pub fn rand_ascii(length: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+// Accepts both plain addresses ("127.0.0.1") and CIDR ranges ("10.0.0.0/8")
+pub fn parse_network(value: &str) -> Option<IpNet> {
+    let value = value.trim();
+
+    match value.parse::<IpNet>() {
+        Ok(network) => Some(network.trunc()),
+        Err(_) => value.parse::<IpAddr>().ok().map(IpNet::from),
+    }
+}
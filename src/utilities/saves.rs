@@ -0,0 +1,385 @@
+// Cloud saves. Every backup is kept as its own blob with a row in `save_versions`, the newest
+// one is what the game gets when syncing. Restoring copies an older backup to the front, so a
+// restore can be undone the same way. With `storage.save_key` set the blobs are encrypted, the
+// account ID goes in as context so a save can't be passed off as someone else's.
+
+use std::{
+    io::{self, ErrorKind},
+    sync::Arc,
+};
+
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{
+    config::Config,
+    types::error::Result,
+    utilities::{
+        crypto, database,
+        game_save::SaveStats,
+        storage::{Blob, Storage},
+    },
+};
+
+// Starts every encrypted save, plain ones are base64 text and never contain a NUL
+const ENCRYPTED_MAGIC: &[u8] = b"\0GDPSAVE1";
+
+fn blob(account_id: i32, version_id: i32) -> Blob {
+    Blob::Save {
+        account_id,
+        version_id,
+    }
+}
+
+// Encrypted with the current key, or left as it is without one
+fn seal(config: &Config, account_id: i32, data: &str) -> Vec<u8> {
+    match crypto::decode_key(&config.storage.save_key) {
+        Some(key) => [
+            ENCRYPTED_MAGIC,
+            &crypto::encrypt(&key, account_id.to_string().as_bytes(), data.as_bytes()),
+        ]
+        .concat(),
+        None => data.as_bytes().to_vec(),
+    }
+}
+
+// Also tells whether the save isn't kept the way the config asks for anymore, because it's
+// plain, encrypted with an old key, or encrypted while there's no key now
+fn open(config: &Config, account_id: i32, stored: Vec<u8>) -> Result<(String, bool)> {
+    let current_key = crypto::decode_key(&config.storage.save_key);
+
+    let (data, outdated) = match stored.strip_prefix(ENCRYPTED_MAGIC) {
+        Some(encrypted) => {
+            let keys = current_key.iter().copied().chain(
+                config
+                    .storage
+                    .old_save_keys
+                    .iter()
+                    .filter_map(|key| crypto::decode_key(key)),
+            );
+
+            keys.enumerate()
+                .find_map(|(i, key)| {
+                    crypto::decrypt(&key, account_id.to_string().as_bytes(), encrypted)
+                        .map(|data| (data, current_key.is_none() || i > 0))
+                })
+                .ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("save of account {account_id} doesn't open with any save key"),
+                    )
+                })?
+        }
+        None => (stored, current_key.is_some()),
+    };
+
+    let data = String::from_utf8(data).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+    Ok((data, outdated))
+}
+
+// Turns a save from before versions into the first version, so it's kept like any other
+async fn import_legacy(
+    db: &PgPool,
+    storage: &Storage,
+    config: &Config,
+    account_id: i32,
+) -> Result<()> {
+    let Some(stored) = storage.get(&Blob::LegacySave(account_id)).await? else {
+        return Ok(());
+    };
+    if !database::get_save_versions(db, account_id)
+        .await?
+        .is_empty()
+    {
+        return Ok(());
+    }
+
+    // Stats weren't kept back then
+    let (data, _) = open(config, account_id, stored)?;
+    let size = data.len() as i32;
+    let stats = tokio::task::spawn_blocking(move || SaveStats::from_save_data(&data))
+        .await
+        .ok()
+        .and_then(|stats| stats.ok())
+        .unwrap_or_default();
+    let version = database::add_save_version(db, account_id, size, &stats).await?;
+    if let Err(e) = storage
+        .rename(
+            &Blob::LegacySave(account_id),
+            &blob(account_id, version.version_id),
+        )
+        .await
+    {
+        database::delete_save_versions(db, &[version.version_id]).await?;
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+pub async fn store(
+    db: &PgPool,
+    storage: &Storage,
+    config: &Config,
+    account_id: i32,
+    data: &str,
+    stats: &SaveStats,
+) -> Result<()> {
+    import_legacy(db, storage, config, account_id).await?;
+
+    let version = database::add_save_version(db, account_id, data.len() as i32, stats).await?;
+    let sealed = seal(config, account_id, data);
+    if let Err(e) = storage
+        .put(&blob(account_id, version.version_id), &sealed)
+        .await
+    {
+        database::delete_save_versions(db, &[version.version_id]).await?;
+        return Err(e.into());
+    }
+
+    // Dropping old versions only after the new one is written means there's always one left
+    let old_versions = database::get_save_versions(db, account_id)
+        .await?
+        .into_iter()
+        .skip(config.limits.save_history as usize)
+        .map(|version| version.version_id)
+        .collect::<Vec<_>>();
+    for &version_id in &old_versions {
+        storage.delete(&blob(account_id, version_id)).await?;
+    }
+    database::delete_save_versions(db, &old_versions).await?;
+
+    Ok(())
+}
+
+// Seals a version again the way the config asks for. Failing only costs the rotation, the
+// version still opens with the old key, so it's logged and left for the next read.
+async fn reseal(storage: &Storage, config: &Config, account_id: i32, save_blob: &Blob, data: &str) {
+    if let Err(e) = storage
+        .put(save_blob, &seal(config, account_id, data))
+        .await
+    {
+        error!("couldn't re-encrypt {}: {e}", save_blob.key());
+    }
+}
+
+// Keys are rotated one account at a time, all of its versions together
+async fn reseal_versions(storage: &Storage, config: &Config, account_id: i32, versions: &[i32]) {
+    for &version_id in versions {
+        let save_blob = blob(account_id, version_id);
+        let stored = match storage.get(&save_blob).await {
+            Ok(Some(stored)) => stored,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("couldn't read {} to re-encrypt it: {e}", save_blob.key());
+                continue;
+            }
+        };
+
+        match open(config, account_id, stored) {
+            Ok((data, true)) => reseal(storage, config, account_id, &save_blob, &data).await,
+            Ok((_, false)) => {}
+            Err(e) => error!("couldn't open {} to re-encrypt it: {e}", save_blob.key()),
+        }
+    }
+}
+
+// The newest save of an account, if it ever backed up
+pub async fn load(
+    db: &PgPool,
+    storage: &Storage,
+    config: &Config,
+    account_id: i32,
+) -> Result<Option<String>> {
+    let versions = database::get_save_versions(db, account_id)
+        .await?
+        .into_iter()
+        .map(|version| version.version_id)
+        .collect::<Vec<_>>();
+    let save_blob = match versions.first() {
+        Some(&version_id) => blob(account_id, version_id),
+        None => Blob::LegacySave(account_id),
+    };
+
+    let Some(stored) = storage.get(&save_blob).await? else {
+        return Ok(None);
+    };
+    let (data, outdated) = open(config, account_id, stored)?;
+
+    // Whatever `spawn_rotation` hasn't gotten to yet
+    if outdated {
+        reseal(storage, config, account_id, &save_blob, &data).await;
+        reseal_versions(
+            storage,
+            config,
+            account_id,
+            versions.get(1..).unwrap_or_default(),
+        )
+        .await;
+    }
+
+    Ok(Some(data))
+}
+
+// Re-encrypts the saves of every account once at startup while there are old keys, so they
+// can be dropped from the config after it's done
+pub fn spawn_rotation(db: PgPool, storage: Storage, config: Arc<Config>) {
+    if config.storage.old_save_keys.is_empty() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let account_ids = match database::get_accounts_with_saves(&db).await {
+            Ok(account_ids) => account_ids,
+            Err(e) => {
+                error!("Failed to list saves to re-encrypt: {e}");
+                return;
+            }
+        };
+
+        for &account_id in &account_ids {
+            match database::get_save_versions(&db, account_id).await {
+                Ok(versions) => {
+                    let version_ids = versions
+                        .iter()
+                        .map(|version| version.version_id)
+                        .collect::<Vec<_>>();
+                    reseal_versions(&storage, &config, account_id, &version_ids).await;
+                }
+                Err(e) => error!("Failed to list the saves of account {account_id}: {e}"),
+            }
+        }
+
+        info!("Re-encrypted the saves of {} accounts", account_ids.len());
+    });
+}
+
+// Returns false if the account has no such version
+pub async fn restore(
+    db: &PgPool,
+    storage: &Storage,
+    config: &Config,
+    account_id: i32,
+    version_id: i32,
+) -> Result<bool> {
+    let versions = database::get_save_versions(db, account_id).await?;
+    let Some(position) = versions
+        .iter()
+        .position(|version| version.version_id == version_id)
+    else {
+        return Ok(false);
+    };
+
+    // Already the one being synced
+    if position == 0 {
+        return Ok(true);
+    }
+
+    let version = &versions[position];
+    let Some(stored) = storage.get(&blob(account_id, version_id)).await? else {
+        return Ok(false);
+    };
+    let (data, _) = open(config, account_id, stored)?;
+    let stats = SaveStats::from(version);
+    store(db, storage, config, account_id, &data, &stats).await?;
+
+    // A version that couldn't be read has no stats, the profile keeps what it shows then
+    if stats != SaveStats::default() {
+        database::update_save_stats(db, account_id, &stats).await?;
+    }
+
+    Ok(true)
+}
+
+pub async fn remove_all(db: &PgPool, storage: &Storage, account_id: i32) -> Result<()> {
+    let version_ids = database::get_save_versions(db, account_id)
+        .await?
+        .into_iter()
+        .map(|version| version.version_id)
+        .collect::<Vec<_>>();
+
+    for &version_id in &version_ids {
+        storage.delete(&blob(account_id, version_id)).await?;
+    }
+    storage.delete(&Blob::LegacySave(account_id)).await?;
+    database::delete_save_versions(db, &version_ids).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const OLD_KEY: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+    const SAVE: &str = "H4sIAAAAAAAAC_save;H4sIAAAAAAAAC_levels";
+
+    fn config(save_key: &str, old_save_keys: &[&str]) -> Config {
+        let mut config = Config::default();
+        config.storage.save_key = save_key.into();
+        config.storage.old_save_keys = old_save_keys.iter().map(|key| key.to_string()).collect();
+        config
+    }
+
+    #[test]
+    fn plain_without_key() {
+        let config = config("", &[]);
+        let sealed = seal(&config, 1, SAVE);
+
+        assert_eq!(sealed, SAVE.as_bytes());
+        assert_eq!(open(&config, 1, sealed).ok(), Some((SAVE.into(), false)));
+    }
+
+    #[test]
+    fn encrypted_with_key() {
+        let config = config(KEY, &[]);
+        let sealed = seal(&config, 1, SAVE);
+
+        assert!(sealed.starts_with(ENCRYPTED_MAGIC));
+        assert!(
+            !sealed
+                .windows(SAVE.len())
+                .any(|window| window == SAVE.as_bytes())
+        );
+        assert_eq!(open(&config, 1, sealed).ok(), Some((SAVE.into(), false)));
+    }
+
+    #[test]
+    fn bound_to_account() {
+        let config = config(KEY, &[]);
+        let sealed = seal(&config, 1, SAVE);
+
+        assert!(open(&config, 2, sealed).is_err());
+    }
+
+    #[test]
+    fn rejects_changed_data() {
+        let config = config(KEY, &[]);
+        let mut sealed = seal(&config, 1, SAVE);
+        *sealed.last_mut().unwrap() ^= 1;
+
+        assert!(open(&config, 1, sealed).is_err());
+    }
+
+    #[test]
+    fn outdated_saves() {
+        let old = seal(&config(OLD_KEY, &[]), 1, SAVE);
+        let rotated = config(KEY, &[OLD_KEY]);
+
+        assert_eq!(
+            open(&rotated, 1, old.clone()).ok(),
+            Some((SAVE.into(), true))
+        );
+        assert!(open(&config(KEY, &[]), 1, old.clone()).is_err());
+        assert_eq!(
+            open(&config("", &[OLD_KEY]), 1, old).ok(),
+            Some((SAVE.into(), true))
+        );
+        assert_eq!(
+            open(&rotated, 1, SAVE.as_bytes().to_vec()).ok(),
+            Some((SAVE.into(), true))
+        );
+    }
+}
@@ -0,0 +1,670 @@
+use sqlx::{Postgres, QueryBuilder};
+
+use super::gd;
+
+// What text searches look through, the same expression as `idx_levels_text_fts` so the index is
+// used. `decode_description` is defined in database.sql.
+const TEXT_VECTOR: &str = "(setweight(to_tsvector('simple', level_name), 'A') || to_tsvector('simple', decode_description(level_desc)))";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+    Int(i32),
+    BigInt(i64),
+    Text(String),
+    IntArray(Vec<i32>),
+    TextArray(Vec<String>),
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::BigInt(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<Vec<i32>> for Value {
+    fn from(value: Vec<i32>) -> Self {
+        Value::IntArray(value)
+    }
+}
+
+impl From<Vec<String>> for Value {
+    fn from(value: Vec<String>) -> Self {
+        Value::TextArray(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Part {
+    Sql(&'static str),
+    Bind(Value),
+}
+
+// A piece of SQL where everything coming from the client is a bound parameter.
+// Only `&'static str` can be pushed as raw SQL, so request data can't end up in the query text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Clause {
+    parts: Vec<Part>,
+}
+
+impl Clause {
+    pub fn new(sql: &'static str) -> Self {
+        Self {
+            parts: vec![Part::Sql(sql)],
+        }
+    }
+
+    pub fn sql(mut self, sql: &'static str) -> Self {
+        self.parts.push(Part::Sql(sql));
+        self
+    }
+
+    pub fn bind(mut self, value: impl Into<Value>) -> Self {
+        self.parts.push(Part::Bind(value.into()));
+        self
+    }
+
+    fn push_to(&self, query: &mut QueryBuilder<'static, Postgres>) {
+        for part in &self.parts {
+            match part {
+                Part::Sql(sql) => query.push(*sql),
+                Part::Bind(Value::Int(value)) => query.push_bind(*value),
+                Part::Bind(Value::BigInt(value)) => query.push_bind(*value),
+                Part::Bind(Value::Text(value)) => query.push_bind(value.clone()),
+                Part::Bind(Value::IntArray(value)) => query.push_bind(value.clone()),
+                Part::Bind(Value::TextArray(value)) => query.push_bind(value.clone()),
+            };
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LevelSearch {
+    game_version: i32,
+    join: Option<&'static str>,
+    filters: Vec<Clause>,
+    order: Clause,
+    descending: bool,
+    // `None` returns every matching level at once (map packs and lists)
+    offset: Option<i64>,
+}
+
+impl LevelSearch {
+    pub fn new(game_version: i32, offset: i64) -> Self {
+        Self {
+            game_version,
+            join: None,
+            filters: Self::base_filters(game_version),
+            order: Clause::new("upload_date"),
+            descending: true,
+            offset: Some(offset),
+        }
+    }
+
+    fn base_filters(game_version: i32) -> Vec<Clause> {
+        vec![
+            Clause::new("unlisted = 0 AND unlisted2 = 0"),
+            Clause::new("levels.game_version <= ").bind(game_version),
+        ]
+    }
+
+    pub fn filter(&mut self, clause: Clause) {
+        self.filters.push(clause);
+    }
+
+    // Drops every filter applied so far except for visibility and game version
+    pub fn reset_filters(&mut self) {
+        self.filters = Self::base_filters(self.game_version);
+    }
+
+    // Drops every filter applied so far, including visibility and game version
+    pub fn replace_filters(&mut self, clause: Clause) {
+        self.filters = vec![clause];
+    }
+
+    pub fn join(&mut self, join: &'static str) {
+        self.join = Some(join);
+    }
+
+    pub fn order_by(&mut self, order: Clause, descending: bool) {
+        self.order = order;
+        self.descending = descending;
+    }
+
+    pub fn without_limit(&mut self) {
+        self.offset = None;
+    }
+
+    pub fn original_only(&mut self) {
+        self.filter(Clause::new("original = 0"));
+    }
+
+    pub fn coins_only(&mut self) {
+        self.filter(Clause::new("star_coins = 1 AND NOT levels.coins = 0"));
+    }
+
+    pub fn completed(&mut self, level_ids: Vec<i32>, uncompleted: bool) {
+        let clause = if uncompleted {
+            Clause::new("NOT levels.level_id = ANY(")
+        } else {
+            Clause::new("levels.level_id = ANY(")
+        };
+
+        self.filter(clause.bind(level_ids).sql(")"));
+    }
+
+    // `song` is the official song index + 1 unless `custom` is set, in which case it's a song ID
+    pub fn song(&mut self, song: i32, custom: bool) {
+        if custom {
+            self.filter(Clause::new("song_id = ").bind(song));
+        } else {
+            self.filter(
+                Clause::new("audio_track = ")
+                    .bind(song - 1)
+                    .sql(" AND song_id = 0"),
+            );
+        }
+    }
+
+    pub fn two_player(&mut self) {
+        self.filter(Clause::new("two_player = 1"));
+    }
+
+    pub fn starred(&mut self) {
+        self.filter(Clause::new("NOT star_stars = 0"));
+    }
+
+    pub fn unstarred(&mut self) {
+        self.filter(Clause::new("star_stars = 0"));
+    }
+
+    pub fn lengths(&mut self, lengths: Vec<i32>) {
+        if lengths.is_empty() {
+            return;
+        }
+
+        self.filter(Clause::new("level_length = ANY(").bind(lengths).sql(")"));
+    }
+
+    // Featured, Epic, Legendary and Mythic are OR-ed together
+    pub fn ratings(&mut self, featured: bool, epic: bool, legendary: bool, mythic: bool) {
+        let ratings = [
+            (featured, "star_featured > 0"),
+            (epic, "star_epic = 1"),
+            (mythic, "star_epic = 2"),
+            (legendary, "star_epic = 3"),
+        ];
+
+        let mut clause: Option<Clause> = None;
+        for (_, sql) in ratings.iter().filter(|(enabled, _)| *enabled) {
+            clause = Some(match clause {
+                Some(clause) => clause.sql(" OR ").sql(sql),
+                None => Clause::new(sql),
+            });
+        }
+
+        if let Some(clause) = clause {
+            self.filter(clause);
+        }
+    }
+
+    // -1 is N/A, -2 is Demon (narrowed down by `demon_filter`), -3 is Auto,
+    // everything else is a difficulty face from 1 (Easy) to 5 (Insane).
+    pub fn difficulty(&mut self, difficulties: &[i32], demon_filter: i32) {
+        if difficulties.contains(&-2) {
+            self.filter(Clause::new("star_demon = 1"));
+
+            if (1..=5).contains(&demon_filter) {
+                let demon = gd::get_demon_from_index(demon_filter);
+                self.filter(Clause::new("star_demon_diff = ").bind(demon.difficulty));
+            }
+        } else if difficulties.contains(&-1) {
+            self.filter(Clause::new("star_difficulty = 0"));
+        } else if difficulties.contains(&-3) {
+            self.filter(Clause::new("star_auto = 1"));
+        } else if !difficulties.is_empty() {
+            let difficulties = difficulties.iter().map(|d| d * 10).collect::<Vec<_>>();
+
+            self.filter(
+                Clause::new("star_difficulty = ANY(")
+                    .bind(difficulties)
+                    .sql(") AND star_auto = 0 AND star_demon = 0"),
+            );
+        }
+    }
+
+    // Matches level names and descriptions by full-text search, typo-tolerant trigram similarity
+    // or substring, ranking the best matches first and breaking ties by likes. A match in the
+    // name counts for more than one in the description.
+    pub fn text(&mut self, text: &str) {
+        let pattern = format!("%{}%", escape_like(text));
+
+        self.filter(
+            Clause::new(TEXT_VECTOR)
+                .sql(" @@ plainto_tsquery('simple', ")
+                .bind(text.to_string())
+                .sql(") OR ")
+                .bind(text.to_string())
+                .sql(" <% level_name OR ")
+                .bind(text.to_string())
+                .sql(" <% decode_description(level_desc) OR level_name ILIKE ")
+                .bind(pattern.clone())
+                .sql(" OR decode_description(level_desc) ILIKE ")
+                .bind(pattern),
+        );
+        self.order_by(
+            Clause::new("ts_rank(")
+                .sql(TEXT_VECTOR)
+                .sql(", plainto_tsquery('simple', ")
+                .bind(text.to_string())
+                .sql(")) DESC, similarity(level_name, ")
+                .bind(text.to_string())
+                .sql(") DESC, likes"),
+            true,
+        );
+    }
+
+    // Ranks levels by likes minus dislikes received in the last week, older likes don't count
+    pub fn trending(&mut self) {
+        self.join("INNER JOIN (SELECT item_id, SUM(CASE WHEN is_like = 1 THEN 1 ELSE -1 END) AS gained FROM actions_likes WHERE type = 1 AND upload_date > NOW() - INTERVAL '7 days' GROUP BY item_id) trending ON levels.level_id = trending.item_id");
+        self.filter(Clause::new("trending.gained > 0"));
+        self.order_by(Clause::new("trending.gained DESC, likes"), true);
+    }
+
+    fn push_where(&self, query: &mut QueryBuilder<'static, Postgres>) {
+        if let Some(join) = self.join {
+            query.push(" ").push(join);
+        }
+
+        query.push(" WHERE ");
+        for filter in &self.filters {
+            query.push("(");
+            filter.push_to(query);
+            query.push(") AND ");
+        }
+        query.push("levels.is_deleted = 0");
+    }
+
+    pub fn select_query(&self) -> QueryBuilder<'static, Postgres> {
+        let mut query = QueryBuilder::new("SELECT levels.* FROM levels");
+        self.push_where(&mut query);
+
+        query.push(" ORDER BY ");
+        self.order.push_to(&mut query);
+        query.push(if self.descending { " DESC" } else { " ASC" });
+
+        if let Some(offset) = self.offset {
+            query.push(" LIMIT 10 OFFSET ").push_bind(offset);
+        }
+
+        query
+    }
+
+    pub fn count_query(&self) -> QueryBuilder<'static, Postgres> {
+        let mut query = QueryBuilder::new("SELECT count(*) FROM levels");
+        self.push_where(&mut query);
+
+        query
+    }
+}
+
+// Parses lists of IDs sent by the client, like "1,2,3" or "(1,2,3)".
+// "-" and an empty string mean that nothing was selected.
+pub fn parse_id_list(value: &str) -> Option<Vec<i32>> {
+    let value = value.trim().trim_start_matches('(').trim_end_matches(')');
+
+    if value.is_empty() || value == "-" {
+        return Some(Vec::new());
+    }
+
+    value
+        .split(',')
+        .map(|id| id.trim().parse::<i32>().ok())
+        .collect()
+}
+
+// Makes user input match literally inside of a LIKE/ILIKE pattern
+pub fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn where_sql(search: &LevelSearch) -> String {
+        let query = search.count_query();
+        let sql = query.sql();
+
+        sql["SELECT count(*) FROM levels WHERE ".len()..].to_string()
+    }
+
+    fn values(search: &LevelSearch) -> Vec<Value> {
+        search
+            .filters
+            .iter()
+            .flat_map(|clause| &clause.parts)
+            .filter_map(|part| match part {
+                Part::Bind(value) => Some(value.clone()),
+                Part::Sql(_) => None,
+            })
+            .collect()
+    }
+
+    const BASE: &str = "(unlisted = 0 AND unlisted2 = 0) AND (levels.game_version <= $1) AND ";
+
+    #[test]
+    fn base_filters() {
+        let search = LevelSearch::new(22, 0);
+
+        assert_eq!(where_sql(&search), format!("{BASE}levels.is_deleted = 0"));
+        assert_eq!(values(&search), vec![Value::Int(22)]);
+    }
+
+    #[test]
+    fn original() {
+        let mut search = LevelSearch::new(22, 0);
+        search.original_only();
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(original = 0) AND levels.is_deleted = 0")
+        );
+    }
+
+    #[test]
+    fn coins() {
+        let mut search = LevelSearch::new(22, 0);
+        search.coins_only();
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(star_coins = 1 AND NOT levels.coins = 0) AND levels.is_deleted = 0")
+        );
+    }
+
+    #[test]
+    fn only_completed() {
+        let mut search = LevelSearch::new(22, 0);
+        search.completed(vec![1, 2, 3], false);
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(levels.level_id = ANY($2)) AND levels.is_deleted = 0")
+        );
+        assert_eq!(values(&search)[1], Value::IntArray(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn uncompleted() {
+        let mut search = LevelSearch::new(22, 0);
+        search.completed(vec![4], true);
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(NOT levels.level_id = ANY($2)) AND levels.is_deleted = 0")
+        );
+        assert_eq!(values(&search)[1], Value::IntArray(vec![4]));
+    }
+
+    #[test]
+    fn official_song() {
+        let mut search = LevelSearch::new(22, 0);
+        search.song(3, false);
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(audio_track = $2 AND song_id = 0) AND levels.is_deleted = 0")
+        );
+        assert_eq!(values(&search)[1], Value::Int(2));
+    }
+
+    #[test]
+    fn custom_song() {
+        let mut search = LevelSearch::new(22, 0);
+        search.song(546561, true);
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(song_id = $2) AND levels.is_deleted = 0")
+        );
+        assert_eq!(values(&search)[1], Value::Int(546561));
+    }
+
+    #[test]
+    fn two_player() {
+        let mut search = LevelSearch::new(22, 0);
+        search.two_player();
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(two_player = 1) AND levels.is_deleted = 0")
+        );
+    }
+
+    #[test]
+    fn star_and_no_star() {
+        let mut search = LevelSearch::new(22, 0);
+        search.starred();
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(NOT star_stars = 0) AND levels.is_deleted = 0")
+        );
+
+        let mut search = LevelSearch::new(22, 0);
+        search.unstarred();
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(star_stars = 0) AND levels.is_deleted = 0")
+        );
+    }
+
+    #[test]
+    fn lengths() {
+        let mut search = LevelSearch::new(22, 0);
+        search.lengths(vec![0, 4]);
+
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(level_length = ANY($2)) AND levels.is_deleted = 0")
+        );
+        assert_eq!(values(&search)[1], Value::IntArray(vec![0, 4]));
+
+        let mut search = LevelSearch::new(22, 0);
+        search.lengths(Vec::new());
+        assert_eq!(where_sql(&search), format!("{BASE}levels.is_deleted = 0"));
+    }
+
+    #[test]
+    fn ratings() {
+        let mut search = LevelSearch::new(22, 0);
+        search.ratings(true, false, false, false);
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(star_featured > 0) AND levels.is_deleted = 0")
+        );
+
+        let mut search = LevelSearch::new(22, 0);
+        search.ratings(true, true, true, true);
+        assert_eq!(
+            where_sql(&search),
+            format!(
+                "{BASE}(star_featured > 0 OR star_epic = 1 OR star_epic = 2 OR star_epic = 3) AND levels.is_deleted = 0"
+            )
+        );
+
+        let mut search = LevelSearch::new(22, 0);
+        search.ratings(false, false, false, false);
+        assert_eq!(where_sql(&search), format!("{BASE}levels.is_deleted = 0"));
+    }
+
+    #[test]
+    fn difficulties() {
+        let mut search = LevelSearch::new(22, 0);
+        search.difficulty(&[1, 3], 0);
+
+        assert_eq!(
+            where_sql(&search),
+            format!(
+                "{BASE}(star_difficulty = ANY($2) AND star_auto = 0 AND star_demon = 0) AND levels.is_deleted = 0"
+            )
+        );
+        assert_eq!(values(&search)[1], Value::IntArray(vec![10, 30]));
+    }
+
+    #[test]
+    fn special_difficulties() {
+        let mut search = LevelSearch::new(22, 0);
+        search.difficulty(&[-1], 0);
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(star_difficulty = 0) AND levels.is_deleted = 0")
+        );
+
+        let mut search = LevelSearch::new(22, 0);
+        search.difficulty(&[-3], 0);
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(star_auto = 1) AND levels.is_deleted = 0")
+        );
+    }
+
+    #[test]
+    fn demon_filter() {
+        let mut search = LevelSearch::new(22, 0);
+        search.difficulty(&[-2], 0);
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(star_demon = 1) AND levels.is_deleted = 0")
+        );
+
+        let mut search = LevelSearch::new(22, 0);
+        search.difficulty(&[-2], 5);
+        assert_eq!(
+            where_sql(&search),
+            format!("{BASE}(star_demon = 1) AND (star_demon_diff = $2) AND levels.is_deleted = 0")
+        );
+        assert_eq!(values(&search)[1], Value::Int(6));
+    }
+
+    #[test]
+    fn text() {
+        let mut search = LevelSearch::new(22, 0);
+        search.text("bloodbath 100%");
+
+        assert_eq!(
+            search.select_query().sql(),
+            format!(
+                "SELECT levels.* FROM levels WHERE {BASE}({TEXT_VECTOR} @@ plainto_tsquery('simple', $2) OR $3 <% level_name OR $4 <% decode_description(level_desc) OR level_name ILIKE $5 OR decode_description(level_desc) ILIKE $6) AND levels.is_deleted = 0 ORDER BY ts_rank({TEXT_VECTOR}, plainto_tsquery('simple', $7)) DESC, similarity(level_name, $8) DESC, likes DESC LIMIT 10 OFFSET $9"
+            )
+        );
+        assert_eq!(values(&search)[1], Value::Text("bloodbath 100%".into()));
+        assert_eq!(values(&search)[4], Value::Text("%bloodbath 100\\%%".into()));
+        assert_eq!(values(&search)[5], Value::Text("%bloodbath 100\\%%".into()));
+    }
+
+    #[test]
+    fn text_covers_descriptions() {
+        let mut search = LevelSearch::new(22, 0);
+        search.text("nine circles");
+
+        let sql = where_sql(&search);
+        assert!(TEXT_VECTOR.contains("to_tsvector('simple', decode_description(level_desc))"));
+        assert!(sql.contains("<% decode_description(level_desc)"));
+        assert!(sql.contains("decode_description(level_desc) ILIKE"));
+    }
+
+    #[test]
+    fn trending() {
+        let mut search = LevelSearch::new(22, 0);
+        search.trending();
+
+        let query = search.select_query();
+        assert!(query.sql().contains("FROM actions_likes WHERE type = 1"));
+        assert!(query.sql().contains(
+            "(trending.gained > 0) AND levels.is_deleted = 0 ORDER BY trending.gained DESC, likes DESC"
+        ));
+    }
+
+    #[test]
+    fn select_pagination_and_order() {
+        let mut search = LevelSearch::new(22, 20);
+        search.order_by(Clause::new("likes"), true);
+
+        assert_eq!(
+            search.select_query().sql(),
+            format!(
+                "SELECT levels.* FROM levels WHERE {BASE}levels.is_deleted = 0 ORDER BY likes DESC LIMIT 10 OFFSET $2"
+            )
+        );
+
+        let mut search = LevelSearch::new(22, 20);
+        search.order_by(
+            Clause::new("array_position(")
+                .bind(vec![3, 1, 2])
+                .sql(", levels.level_id)"),
+            false,
+        );
+        search.without_limit();
+
+        assert_eq!(
+            search.select_query().sql(),
+            format!(
+                "SELECT levels.* FROM levels WHERE {BASE}levels.is_deleted = 0 ORDER BY array_position($2, levels.level_id) ASC"
+            )
+        );
+    }
+
+    #[test]
+    fn joins_and_resets() {
+        let mut search = LevelSearch::new(22, 0);
+        search.starred();
+        search.reset_filters();
+        search.join("INNER JOIN daily_features ON levels.level_id = daily_features.level_id");
+
+        assert_eq!(
+            search.count_query().sql(),
+            format!(
+                "SELECT count(*) FROM levels INNER JOIN daily_features ON levels.level_id = daily_features.level_id WHERE {BASE}levels.is_deleted = 0"
+            )
+        );
+
+        search.replace_filters(Clause::new("levels.level_id = ").bind(5));
+        assert_eq!(
+            where_sql(&LevelSearch {
+                join: None,
+                ..search.clone()
+            }),
+            "(levels.level_id = $1) AND levels.is_deleted = 0"
+        );
+    }
+
+    #[test]
+    fn id_lists() {
+        assert_eq!(parse_id_list("1,2,3"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_id_list("(1, 2,3)"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_id_list("-"), Some(vec![]));
+        assert_eq!(parse_id_list(""), Some(vec![]));
+        assert_eq!(parse_id_list("1,2) OR 1=1 --"), None);
+        assert_eq!(parse_id_list("1,,2"), None);
+    }
+
+    #[test]
+    fn like_escaping() {
+        assert_eq!(escape_like("100%_done\\"), "100\\%\\_done\\\\");
+    }
+}
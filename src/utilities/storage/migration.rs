@@ -0,0 +1,142 @@
+// Moves data kept the old ways into the configured backend: the files under `storage.levels`
+// and `storage.saves` when switching away from the filesystem, and level data still in
+// `levels.level_string` from before there was storage. Run with `gdps migrate-storage` while
+// the server is stopped. Nothing already in the backend is replaced, so it can simply be run
+// again after an interruption.
+
+use std::path::Path;
+
+use sqlx::PgPool;
+use tokio::fs;
+use tracing::{info, warn};
+
+use super::{Blob, Storage, StorageError};
+use crate::config::{StorageBackendKind, StorageConfig};
+
+pub async fn migrate(
+    config: &StorageConfig,
+    db: &PgPool,
+    storage: &Storage,
+) -> Result<(), StorageError> {
+    if config.backend != StorageBackendKind::Filesystem {
+        let levels = copy_files(storage, &config.levels, |name| {
+            name.parse().ok().map(Blob::Level)
+        })
+        .await?;
+        let deleted_levels = copy_files(storage, &config.levels.join("deleted"), |name| {
+            name.parse().ok().map(Blob::DeletedLevel)
+        })
+        .await?;
+        let saves = copy_files(storage, &config.saves, parse_save_name).await?;
+
+        info!(
+            "Copied {levels} levels, {deleted_levels} deleted levels and {saves} saves from the filesystem"
+        );
+    }
+
+    let level_ids = sqlx::query_scalar!(
+        "SELECT level_id FROM levels WHERE level_string IS NOT NULL ORDER BY level_id"
+    )
+    .fetch_all(db)
+    .await
+    .map_err(StorageError::Database)?;
+
+    for &level_id in &level_ids {
+        let level = sqlx::query!(
+            "SELECT level_string, is_deleted FROM levels WHERE level_id = $1",
+            level_id
+        )
+        .fetch_one(db)
+        .await
+        .map_err(StorageError::Database)?;
+
+        let blob = match level.is_deleted {
+            0 => Blob::Level(level_id),
+            _ => Blob::DeletedLevel(level_id),
+        };
+        // A blob is only ever written by an upload newer than the row
+        if storage.get(&blob).await?.is_none() {
+            let data = level.level_string.unwrap_or_default();
+            storage.put(&blob, data.as_bytes()).await?;
+        }
+
+        sqlx::query!(
+            "UPDATE levels SET level_string = NULL WHERE level_id = $1",
+            level_id
+        )
+        .execute(db)
+        .await
+        .map_err(StorageError::Database)?;
+    }
+
+    info!(
+        "Moved the data of {} levels out of 'levels'",
+        level_ids.len()
+    );
+
+    Ok(())
+}
+
+// `{account_id}-{version_id}`, or only the account ID for a save from before versions
+fn parse_save_name(name: &str) -> Option<Blob> {
+    match name.split_once('-') {
+        Some((account_id, version_id)) => Some(Blob::Save {
+            account_id: account_id.parse().ok()?,
+            version_id: version_id.parse().ok()?,
+        }),
+        None => name.parse().ok().map(Blob::LegacySave),
+    }
+}
+
+// Returns how many files were copied. Anything that isn't named like a blob, such as a temporary
+// file left by an interrupted write, is skipped.
+async fn copy_files(
+    storage: &Storage,
+    directory: &Path,
+    parse_name: impl Fn(&str) -> Option<Blob>,
+) -> Result<usize, StorageError> {
+    let mut entries = match fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(StorageError::Io(e)),
+    };
+
+    let mut copied = 0;
+    while let Some(entry) = entries.next_entry().await.map_err(StorageError::Io)? {
+        if !entry.file_type().await.map_err(StorageError::Io)?.is_file() {
+            continue;
+        }
+        let Some(blob) = entry.file_name().to_str().and_then(&parse_name) else {
+            warn!("Skipping '{}'", entry.path().display());
+            continue;
+        };
+
+        if storage.get(&blob).await?.is_some() {
+            continue;
+        }
+        let data = fs::read(entry.path()).await.map_err(StorageError::Io)?;
+        storage.put(&blob, &data).await?;
+        copied += 1;
+    }
+
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_names() {
+        assert_eq!(
+            parse_save_name("12-34"),
+            Some(Blob::Save {
+                account_id: 12,
+                version_id: 34
+            })
+        );
+        assert_eq!(parse_save_name("12"), Some(Blob::LegacySave(12)));
+        assert_eq!(parse_save_name("12-34.0123456789abcdef.tmp"), None);
+        assert_eq!(parse_save_name("12.tmp"), None);
+    }
+}
@@ -0,0 +1,222 @@
+// Level data and saves. Handlers name what they want with a `Blob` and hand it to whichever
+// backend the config picks: files on disk, the `blobs` table, or an S3-compatible bucket.
+
+mod migration;
+mod s3;
+
+use std::{
+    fmt,
+    future::Future,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+};
+
+use sqlx::PgPool;
+use tokio::fs;
+
+use crate::config::{StorageBackendKind, StorageConfig};
+
+pub use migration::migrate;
+pub use s3::S3Store;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blob {
+    Level(i32),
+    // Kept aside after the level is deleted, it's still part of the creator's data export
+    DeletedLevel(i32),
+    Save { account_id: i32, version_id: i32 },
+    // The only save of an account from before there were versions
+    LegacySave(i32),
+}
+
+impl Blob {
+    // Also the object name in a bucket and the key in `blobs`
+    pub fn key(&self) -> String {
+        match self {
+            Blob::Level(level_id) => format!("levels/{level_id}"),
+            Blob::DeletedLevel(level_id) => format!("levels/deleted/{level_id}"),
+            Blob::Save {
+                account_id,
+                version_id,
+            } => format!("saves/{account_id}-{version_id}"),
+            Blob::LegacySave(account_id) => format!("saves/{account_id}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Database(sqlx::Error),
+    S3(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "io: {e}"),
+            StorageError::Database(e) => write!(f, "database: {e}"),
+            StorageError::S3(e) => write!(f, "s3: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+pub type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, StorageError>> + Send + 'a>>;
+
+pub trait BlobStore: Send + Sync {
+    // None if there's nothing stored under it
+    fn get<'a>(&'a self, blob: &'a Blob) -> StorageFuture<'a, Option<Vec<u8>>>;
+    // Replaces what was there before, readers never see half of it
+    fn put<'a>(&'a self, blob: &'a Blob, data: &'a [u8]) -> StorageFuture<'a, ()>;
+    // Deleting something that isn't there isn't an error
+    fn delete<'a>(&'a self, blob: &'a Blob) -> StorageFuture<'a, ()>;
+}
+
+// Shared by the handlers through an `Extension`
+#[derive(Clone)]
+pub struct Storage {
+    store: Arc<dyn BlobStore>,
+}
+
+impl Storage {
+    pub fn new(config: &StorageConfig, db: PgPool) -> Result<Self, StorageError> {
+        let store: Arc<dyn BlobStore> = match config.backend {
+            StorageBackendKind::Filesystem => Arc::new(FilesystemStore {
+                levels: config.levels.clone(),
+                saves: config.saves.clone(),
+            }),
+            StorageBackendKind::Database => Arc::new(DatabaseStore { db }),
+            StorageBackendKind::S3 => Arc::new(S3Store::new(config)?),
+        };
+
+        Ok(Self { store })
+    }
+
+    pub async fn get(&self, blob: &Blob) -> Result<Option<Vec<u8>>, StorageError> {
+        self.store.get(blob).await
+    }
+
+    pub async fn put(&self, blob: &Blob, data: &[u8]) -> Result<(), StorageError> {
+        self.store.put(blob, data).await
+    }
+
+    pub async fn delete(&self, blob: &Blob) -> Result<(), StorageError> {
+        self.store.delete(blob).await
+    }
+
+    // A copy followed by a delete, not every backend can rename. Returns false if there was
+    // nothing to move.
+    pub async fn rename(&self, from: &Blob, to: &Blob) -> Result<bool, StorageError> {
+        let Some(data) = self.get(from).await? else {
+            return Ok(false);
+        };
+
+        self.put(to, &data).await?;
+        self.delete(from).await?;
+
+        Ok(true)
+    }
+}
+
+// Keeps the layout from before there were backends, so existing files stay where they are
+pub struct FilesystemStore {
+    levels: PathBuf,
+    saves: PathBuf,
+}
+
+impl FilesystemStore {
+    fn path(&self, blob: &Blob) -> PathBuf {
+        match blob {
+            Blob::Level(level_id) => self.levels.join(level_id.to_string()),
+            Blob::DeletedLevel(level_id) => self.levels.join("deleted").join(level_id.to_string()),
+            Blob::Save {
+                account_id,
+                version_id,
+            } => self.saves.join(format!("{account_id}-{version_id}")),
+            Blob::LegacySave(account_id) => self.saves.join(account_id.to_string()),
+        }
+    }
+}
+
+impl BlobStore for FilesystemStore {
+    fn get<'a>(&'a self, blob: &'a Blob) -> StorageFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move {
+            match fs::read(self.path(blob)).await {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(StorageError::Io(e)),
+            }
+        })
+    }
+
+    fn put<'a>(&'a self, blob: &'a Blob, data: &'a [u8]) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            // Through a temporary file, so an interrupted write can't leave half of it behind.
+            // Named per write, two writes of the same blob at once mustn't share one.
+            let path = self.path(blob);
+            let temporary_path = path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+
+            let result = match fs::write(&temporary_path, data).await {
+                Ok(()) => fs::rename(&temporary_path, &path).await,
+                Err(e) => Err(e),
+            };
+            if result.is_err() {
+                let _ = fs::remove_file(&temporary_path).await;
+            }
+
+            result.map_err(StorageError::Io)
+        })
+    }
+
+    fn delete<'a>(&'a self, blob: &'a Blob) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            match fs::remove_file(self.path(blob)).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(StorageError::Io(e)),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+pub struct DatabaseStore {
+    db: PgPool,
+}
+
+impl BlobStore for DatabaseStore {
+    fn get<'a>(&'a self, blob: &'a Blob) -> StorageFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move {
+            sqlx::query_scalar!("SELECT data FROM blobs WHERE key = $1", blob.key())
+                .fetch_optional(&self.db)
+                .await
+                .map_err(StorageError::Database)
+        })
+    }
+
+    fn put<'a>(&'a self, blob: &'a Blob, data: &'a [u8]) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query!(
+                "INSERT INTO blobs (key, data) VALUES ($1, $2) ON CONFLICT (key) DO UPDATE SET data = EXCLUDED.data",
+                blob.key(),
+                data
+            )
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(StorageError::Database)
+        })
+    }
+
+    fn delete<'a>(&'a self, blob: &'a Blob) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query!("DELETE FROM blobs WHERE key = $1", blob.key())
+                .execute(&self.db)
+                .await
+                .map(|_| ())
+                .map_err(StorageError::Database)
+        })
+    }
+}
@@ -0,0 +1,278 @@
+// RobTop's key/value format: `key<sep>value<sep>key<sep>value...`, where keys are numbers and the
+// separator depends on the object (`:` for levels and users, `~` for comments, `~|~` for songs).
+// Objects are declared with `wire_object!`, which ties every key to a field so a layout can't get
+// out of step with its values, and refuses to compile if a key is used twice.
+
+use std::fmt::Write;
+
+pub trait WireValue: Sized {
+    fn write_to(&self, out: &mut String);
+    #[allow(unused)]
+    fn parse(value: &str) -> Option<Self>;
+
+    // Optional values leave their key out entirely
+    fn is_present(&self) -> bool {
+        true
+    }
+}
+
+pub trait WireObject: Sized {
+    const SEPARATOR: &'static str;
+
+    fn encode(&self) -> String;
+    // Unknown keys are skipped, missing ones are left at their default
+    #[allow(unused)]
+    fn decode(input: &str) -> Option<Self>;
+}
+
+macro_rules! display_value {
+    ($($ty:ty),*) => {
+        $(
+            impl WireValue for $ty {
+                fn write_to(&self, out: &mut String) {
+                    let _ = write!(out, "{self}");
+                }
+
+                fn parse(value: &str) -> Option<Self> {
+                    // The game leaves some numbers empty when they're 0
+                    if value.is_empty() {
+                        return Some(Self::default());
+                    }
+
+                    value.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+display_value!(i16, i32, i64, u8);
+
+impl WireValue for String {
+    fn write_to(&self, out: &mut String) {
+        out.push_str(self);
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        Some(value.to_string())
+    }
+}
+
+impl WireValue for bool {
+    fn write_to(&self, out: &mut String) {
+        out.push(if *self { '1' } else { '0' });
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        Some(i32::parse(value)? != 0)
+    }
+}
+
+impl<T: WireValue> WireValue for Option<T> {
+    fn write_to(&self, out: &mut String) {
+        if let Some(value) = self {
+            value.write_to(out);
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        T::parse(value).map(Some)
+    }
+
+    fn is_present(&self) -> bool {
+        self.is_some()
+    }
+}
+
+pub const fn has_unique_keys(keys: &[u32]) -> bool {
+    let mut i = 0;
+    while i < keys.len() {
+        let mut j = i + 1;
+        while j < keys.len() {
+            if keys[i] == keys[j] {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+// Appends `key<sep>value` to an object being encoded
+pub fn write_pair(out: &mut String, separator: &str, key: u32, value: &impl WireValue) {
+    if !value.is_present() {
+        return;
+    }
+    if !out.is_empty() {
+        out.push_str(separator);
+    }
+
+    let _ = write!(out, "{key}{separator}");
+    value.write_to(out);
+}
+
+// Lists of objects are separated by `|`
+pub fn encode_list<T: WireObject>(objects: &[T]) -> String {
+    objects
+        .iter()
+        .map(WireObject::encode)
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+macro_rules! wire_object {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident($separator:literal) {
+            $($key:literal => $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Default, Clone, PartialEq)]
+        $vis struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        const _: () = assert!(
+            $crate::utilities::wire::has_unique_keys(&[$($key),*]),
+            concat!("`", stringify!($name), "` uses the same key twice")
+        );
+
+        impl $crate::utilities::wire::WireObject for $name {
+            const SEPARATOR: &'static str = $separator;
+
+            fn encode(&self) -> String {
+                let mut out = String::new();
+                $($crate::utilities::wire::write_pair(&mut out, Self::SEPARATOR, $key, &self.$field);)*
+                out
+            }
+
+            fn decode(input: &str) -> Option<Self> {
+                let mut object = Self::default();
+                let mut parts = input.split(Self::SEPARATOR);
+
+                while let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                    match key.parse::<u32>().ok()? {
+                        $($key => object.$field = $crate::utilities::wire::WireValue::parse(value)?,)*
+                        _ => (),
+                    }
+                }
+
+                Some(object)
+            }
+        }
+    };
+}
+
+pub(crate) use wire_object;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    wire_object! {
+        struct Example(":") {
+            1 => id: i32,
+            2 => name: String,
+            3 => hidden: bool,
+            4 => extra: Option<i32>,
+        }
+    }
+
+    wire_object! {
+        struct Song("~|~") {
+            1 => id: i32,
+            2 => name: String,
+        }
+    }
+
+    #[test]
+    fn encodes_in_declaration_order() {
+        let example = Example {
+            id: 5,
+            name: "Sonic Wave".into(),
+            hidden: true,
+            extra: None,
+        };
+
+        assert_eq!(example.encode(), "1:5:2:Sonic Wave:3:1");
+    }
+
+    #[test]
+    fn optional_keys_are_written_when_set() {
+        let example = Example {
+            extra: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(example.encode(), "1:0:2::3:0:4:0");
+    }
+
+    #[test]
+    fn multi_character_separators() {
+        let song = Song {
+            id: 777,
+            name: "Song".into(),
+        };
+
+        assert_eq!(song.encode(), "1~|~777~|~2~|~Song");
+        assert_eq!(Song::decode(&song.encode()), Some(song));
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let example = Example {
+            id: 12,
+            name: "Bloodbath".into(),
+            hidden: false,
+            extra: Some(3),
+        };
+
+        assert_eq!(Example::decode(&example.encode()), Some(example));
+    }
+
+    #[test]
+    fn decode_skips_unknown_and_fills_missing_keys() {
+        let example = Example::decode("99:x:2:Level:1:7").unwrap();
+
+        assert_eq!(example.id, 7);
+        assert_eq!(example.name, "Level");
+        assert!(!example.hidden);
+        assert_eq!(example.extra, None);
+    }
+
+    #[test]
+    fn decode_rejects_malformed_values() {
+        assert_eq!(Example::decode("1:abc"), None);
+        assert_eq!(Example::decode("key:1"), None);
+    }
+
+    #[test]
+    fn empty_numbers_are_zero() {
+        assert_eq!(Example::decode("1::3:").unwrap().id, 0);
+    }
+
+    #[test]
+    fn lists_are_joined_with_pipes() {
+        let list = [
+            Example {
+                id: 1,
+                ..Default::default()
+            },
+            Example {
+                id: 2,
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(encode_list(&list), "1:1:2::3:0|1:2:2::3:0");
+    }
+
+    #[test]
+    fn duplicate_keys_are_detected() {
+        assert!(has_unique_keys(&[1, 2, 3]));
+        assert!(!has_unique_keys(&[1, 2, 1]));
+    }
+}